@@ -0,0 +1,286 @@
+//! Measurement timeout for `touch.pio`, sized to the pad during warmup.
+//!
+//! The program counts charge-discharge cycles (X, down from `u32::MAX`)
+//! until the Y timeout runs out. A large pad charges slowly and completes
+//! few cycles, losing resolution, or none at all; a small pad completes far
+//! more than needed and wastes time. `AutoRange` rescales the timeout from
+//! the first few samples until each measurement counts about
+//! `DEFAULT_TARGET_CYCLES`, then holds it so raw counts stay comparable.
+
+/// Timeout `touch.pio` starts with (~3.2 ms, ~300 samples/s at 125 MHz).
+pub const DEFAULT_TIMEOUT: u32 = 200_000;
+/// Samples per second at `DEFAULT_TIMEOUT`. A measurement takes about two
+/// PIO cycles per timeout count, so the rate goes inversely with it.
+pub const DEFAULT_SAMPLE_RATE: u32 = 300;
+/// Shortest timeout (~1,200 samples/s).
+pub const MIN_TIMEOUT: u32 = 50_000;
+/// Longest timeout (~75 samples/s).
+pub const MAX_TIMEOUT: u32 = 800_000;
+/// Default cycles per measurement to range for.
+pub const DEFAULT_TARGET_CYCLES: u32 = 2048;
+/// Consecutive samples within range before the timeout is locked.
+pub const SETTLE_SAMPLES: u32 = 8;
+/// Samples after which the timeout is locked whether or not it settled.
+pub const RANGE_SAMPLES: u32 = 128;
+
+/// One raw value from `touch.pio`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Reading {
+    /// A measurement, as the raw X count.
+    Count(u32),
+    /// The timeout ran out before a single cycle completed (the `jmp done`
+    /// paths with X untouched): the pin never charged, so the value says
+    /// nothing about the pad.
+    Timeout,
+}
+
+impl Reading {
+    pub fn from_raw(raw: u32) -> Self {
+        if raw == u32::MAX {
+            Reading::Timeout
+        } else {
+            Reading::Count(raw)
+        }
+    }
+
+    /// Charge-discharge cycles completed.
+    pub fn cycles(self) -> u32 {
+        match self {
+            Reading::Count(raw) => !raw,
+            Reading::Timeout => 0,
+        }
+    }
+}
+
+pub struct AutoRange {
+    timeout: u32,
+    target: u32,
+    settled: u32,
+    samples: u32,
+    locked: bool,
+}
+
+impl Default for AutoRange {
+    fn default() -> Self {
+        Self::new(DEFAULT_TIMEOUT)
+    }
+}
+
+impl AutoRange {
+    /// Start ranging from `timeout`, clamped to `MIN_TIMEOUT..=MAX_TIMEOUT`.
+    pub fn new(timeout: u32) -> Self {
+        AutoRange {
+            timeout: timeout.clamp(MIN_TIMEOUT, MAX_TIMEOUT),
+            target: DEFAULT_TARGET_CYCLES,
+            settled: 0,
+            samples: 0,
+            locked: false,
+        }
+    }
+
+    /// Set the cycles per measurement to range for.
+    pub fn set_target(&mut self, cycles: u32) {
+        self.target = cycles.max(1);
+    }
+
+    /// Timeout to feed `touch.pio` for the next measurement.
+    pub fn timeout(&self) -> u32 {
+        self.timeout
+    }
+
+    /// Samples per second at the current timeout.
+    pub fn sample_rate(&self) -> u32 {
+        (DEFAULT_SAMPLE_RATE as u64 * DEFAULT_TIMEOUT as u64 / self.timeout as u64) as u32
+    }
+
+    /// Whether ranging has finished and the timeout is fixed.
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Classify a raw value measured with the current `timeout()` and, until
+    /// locked, rescale the timeout towards the target.
+    pub fn update(&mut self, raw: u32) -> Reading {
+        let reading = Reading::from_raw(raw);
+        if self.locked {
+            return reading;
+        }
+        self.samples += 1;
+
+        let next = self.rescale(reading.cycles());
+        if next == self.timeout {
+            self.settled += 1;
+        } else {
+            self.timeout = next;
+            self.settled = 0;
+        }
+        if self.settled >= SETTLE_SAMPLES || self.samples >= RANGE_SAMPLES {
+            self.locked = true;
+        }
+        reading
+    }
+
+    /// Timeout that would count `target` cycles, or the current one if the
+    /// count is already within 25% of it.
+    fn rescale(&self, cycles: u32) -> u32 {
+        let band = self.target / 4;
+        if cycles.abs_diff(self.target) <= band {
+            return self.timeout;
+        }
+        let next = if cycles == 0 {
+            self.timeout.saturating_mul(2)
+        } else {
+            (self.timeout as u64 * self.target as u64 / cycles as u64).min(MAX_TIMEOUT as u64) as u32
+        };
+        next.clamp(MIN_TIMEOUT, MAX_TIMEOUT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Raw value from a pad whose cycles cost `per_cycle` timeout counts.
+    fn measure(timeout: u32, per_cycle: u32) -> u32 {
+        !(timeout / per_cycle)
+    }
+
+    /// Range against a pad until locked, returning the samples it took.
+    fn range(auto: &mut AutoRange, per_cycle: impl Fn(u32) -> u32) -> u32 {
+        let mut n = 0;
+        while !auto.is_locked() {
+            let raw = measure(auto.timeout(), per_cycle(n));
+            auto.update(raw);
+            n += 1;
+        }
+        n
+    }
+
+    fn assert_in_band(auto: &AutoRange, per_cycle: u32) {
+        let cycles = auto.timeout() / per_cycle;
+        let band = DEFAULT_TARGET_CYCLES / 4;
+        assert!(
+            cycles.abs_diff(DEFAULT_TARGET_CYCLES) <= band,
+            "timeout {} counts {} cycles",
+            auto.timeout(),
+            cycles
+        );
+    }
+
+    // --- Readings ---
+
+    #[test]
+    fn test_untouched_x_is_timeout() {
+        assert_eq!(Reading::from_raw(u32::MAX), Reading::Timeout);
+        assert_eq!(Reading::Timeout.cycles(), 0);
+    }
+
+    #[test]
+    fn test_counts_are_cycles_below_max() {
+        assert_eq!(Reading::from_raw(u32::MAX - 1), Reading::Count(u32::MAX - 1));
+        assert_eq!(Reading::from_raw(u32::MAX - 1).cycles(), 1);
+        assert_eq!(Reading::from_raw(!3000).cycles(), 3000);
+    }
+
+    #[test]
+    fn test_update_reports_timeouts() {
+        let mut auto = AutoRange::default();
+        assert_eq!(auto.update(u32::MAX), Reading::Timeout);
+        assert_eq!(auto.update(!2048), Reading::Count(!2048));
+    }
+
+    // --- Ranging ---
+
+    #[test]
+    fn test_pad_in_range_keeps_default() {
+        let mut auto = AutoRange::default();
+        let samples = range(&mut auto, |_| 100);
+        assert_eq!(auto.timeout(), DEFAULT_TIMEOUT);
+        assert_eq!(samples, SETTLE_SAMPLES);
+    }
+
+    #[test]
+    fn test_small_pad_shortens_timeout() {
+        let mut auto = AutoRange::default();
+        range(&mut auto, |_| 30);
+        assert!(auto.timeout() < DEFAULT_TIMEOUT);
+        assert_in_band(&auto, 30);
+    }
+
+    #[test]
+    fn test_large_pad_lengthens_timeout() {
+        let mut auto = AutoRange::default();
+        range(&mut auto, |_| 300);
+        assert!(auto.timeout() > DEFAULT_TIMEOUT);
+        assert_in_band(&auto, 300);
+    }
+
+    #[test]
+    fn test_timeouts_double_the_timeout() {
+        let mut auto = AutoRange::default();
+        // Slow enough that no cycle completes in the default timeout
+        auto.update(measure(auto.timeout(), 250_000));
+        assert_eq!(auto.timeout(), DEFAULT_TIMEOUT * 2);
+        range(&mut auto, |_| 250_000);
+        assert_eq!(auto.timeout(), MAX_TIMEOUT);
+    }
+
+    #[test]
+    fn test_timeout_stays_within_limits() {
+        let mut auto = AutoRange::default();
+        range(&mut auto, |_| 1);
+        assert_eq!(auto.timeout(), MIN_TIMEOUT);
+
+        let mut auto = AutoRange::default();
+        range(&mut auto, |_| 5000);
+        assert_eq!(auto.timeout(), MAX_TIMEOUT);
+    }
+
+    #[test]
+    fn test_noisy_pad_settles() {
+        let mut auto = AutoRange::default();
+        // ±10% jitter on the cost of a cycle
+        let samples = range(&mut auto, |n| [200, 220, 180, 210, 190][n as usize % 5]);
+        assert!(samples < RANGE_SAMPLES, "took {} samples", samples);
+        assert_in_band(&auto, 200);
+    }
+
+    #[test]
+    fn test_gives_up_after_range_samples() {
+        let mut auto = AutoRange::default();
+        // Wildly varying pad never stays in range
+        let samples = range(&mut auto, |n| if n % 2 == 0 { 20 } else { 2000 });
+        assert_eq!(samples, RANGE_SAMPLES);
+    }
+
+    #[test]
+    fn test_locked_timeout_does_not_change() {
+        let mut auto = AutoRange::default();
+        range(&mut auto, |_| 100);
+        auto.update(u32::MAX);
+        auto.update(measure(auto.timeout(), 10));
+        assert_eq!(auto.timeout(), DEFAULT_TIMEOUT);
+    }
+
+    #[test]
+    fn test_custom_target() {
+        let mut auto = AutoRange::default();
+        auto.set_target(500);
+        range(&mut auto, |_| 100);
+        let cycles = auto.timeout() / 100;
+        assert!(cycles.abs_diff(500) <= 125, "{} cycles", cycles);
+    }
+
+    #[test]
+    fn test_sample_rate_follows_timeout() {
+        assert_eq!(AutoRange::default().sample_rate(), DEFAULT_SAMPLE_RATE);
+        assert_eq!(AutoRange::new(MIN_TIMEOUT).sample_rate(), 1200);
+        assert_eq!(AutoRange::new(MAX_TIMEOUT).sample_rate(), 75);
+        let mut auto = AutoRange::default();
+        // Twice the default cycle time: half the default rate, give or take
+        // the 25% band ranging settles within
+        range(&mut auto, |_| 200);
+        let rate = auto.sample_rate();
+        assert!(rate.abs_diff(150) <= 150 / 4, "{} samples/s", rate);
+    }
+}
@@ -1,56 +1,557 @@
+use crate::autorange::{Reading, DEFAULT_SAMPLE_RATE, MAX_TIMEOUT};
+use crate::filter::{rescale, rescale_shift, Filter, FilterChain, FilterStage, MAX_STAGES};
+
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum TouchState {
     Warmup,
     Idle,
     Short,
     Long,
+    /// Touched for longer than `max_hold`. The touched level has become the
+    /// new baseline.
+    Stuck,
+}
+
+/// Discrete touch events, derived from the per-sample `TouchState`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TouchEvent {
+    /// Finger confirmed on the pad (past the tap minimum).
+    Press,
+    /// Finger lifted after a `Press`.
+    Release,
+    /// Released before the long threshold. Follows `Release`.
+    Tap,
+    /// Held past the long threshold. Taps still waiting for the multi-tap
+    /// window are dropped.
+    LongPressStart,
+    /// Every `hold_repeat` samples while a long press is held.
+    HoldRepeat,
+    /// Released after a `LongPressStart`. Follows `Release`.
+    LongPressEnd,
+    /// A tap with no second tap inside the multi-tap window.
+    SingleTap,
+    /// Two taps, each within the multi-tap window of the last.
+    DoubleTap,
+    /// Three taps, each within the multi-tap window of the last. Reported
+    /// straight away, since nothing longer is recognised.
+    TripleTap,
+    /// Held past `max_hold`, most likely by something resting on the pad.
+    /// Replaces `Release`/`LongPressEnd` for that touch; detection resumes
+    /// against the new baseline.
+    Stuck,
+    /// A hand came within range: the level dropped below the proximity
+    /// threshold. Only reported when proximity detection is configured.
+    Approach,
+    /// The hand moved away again after an `Approach`.
+    Leave,
+    /// The pad has given faulty readings for `fault_samples` samples in a
+    /// row, or stopped sending samples. No touch events are reported until
+    /// it recovers.
+    Fault(SensorFault),
+    /// Healthy readings again for `fault_samples` samples. The pad warms up
+    /// from scratch.
+    Recovered,
+}
+
+/// What is wrong with a pad that is not giving usable readings.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SensorFault {
+    /// More cycles than `fault_cycles` allows: next to no capacitance on the
+    /// pin, as when the pad is disconnected.
+    Open,
+    /// Every measurement times out: the pin never charges, as when it is
+    /// shorted to ground.
+    Shorted,
+    /// Fewer cycles than `fault_cycles` allows: the pad barely completes a
+    /// cycle per measurement, leaving no room to see a touch.
+    Saturated,
+    /// No measurements are arriving at all (see `Channel::stalled`).
+    NoSamples,
+}
+
+const MAX_EVENTS: usize = 4;
+
+/// Events produced by a single sample, in the order they happened.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct Events {
+    events: [Option<TouchEvent>; MAX_EVENTS],
+    next: usize,
+}
+
+impl Events {
+    fn push(&mut self, event: TouchEvent) {
+        if let Some(slot) = self.events.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some(event);
+        }
+    }
+}
+
+impl Iterator for Events {
+    type Item = TouchEvent;
+
+    fn next(&mut self) -> Option<TouchEvent> {
+        let event = *self.events.get(self.next)?;
+        self.next += 1;
+        event
+    }
+}
+
+/// Fixed-point 1.0 for normalised pad levels (Q16).
+pub const LEVEL_ONE: u32 = 1 << 16;
+
+/// Tuning for one touch pad. Durations are in samples at `sample_rate`,
+/// except the multi-tap window, which is in milliseconds.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ChannelConfig {
+    sample_rate: u32,
+    warmup: u32,
+    min_window: u32,
+    debounce: u32,
+    touch_threshold: u32,
+    tap_min: u32,
+    long_threshold: u32,
+    hold_repeat: u32,
+    multi_tap_window_ms: u32,
+    baseline_shift: u32,
+    delta_decay_shift: u32,
+    max_hold: u32,
+    filters: [Option<FilterStage>; MAX_STAGES],
+    proximity_threshold: Option<u32>,
+    fault_cycles: Option<(u32, u32)>,
+    fault_samples: u32,
+}
+
+impl Default for ChannelConfig {
+    fn default() -> Self {
+        ChannelConfig {
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            warmup: 100,
+            min_window: 24,
+            debounce: 200,
+            touch_threshold: LEVEL_ONE / 2,
+            tap_min: 5,
+            long_threshold: 300,
+            hold_repeat: 100,
+            multi_tap_window_ms: 0,
+            baseline_shift: 7,
+            delta_decay_shift: 9,
+            max_hold: 9000,
+            filters: [None; MAX_STAGES],
+            proximity_threshold: None,
+            fault_cycles: None,
+            fault_samples: 300,
+        }
+    }
+}
+
+impl ChannelConfig {
+    pub fn builder() -> ChannelConfigBuilder {
+        ChannelConfigBuilder {
+            config: ChannelConfig::default(),
+            filter_count: 0,
+        }
+    }
+
+    /// Samples per second the durations are counted at.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// This config for a pad sampled `sample_rate` times a second, with every
+    /// duration, time constant and filter rescaled to last as long as
+    /// before. Auto-ranging sets each pad's rate, anywhere from ~75 to
+    /// ~1,200 samples/s.
+    pub fn at_sample_rate(&self, sample_rate: u32) -> Result<ChannelConfig, ConfigError> {
+        if sample_rate == 0 {
+            return Err(ConfigError::ZeroSampleRate);
+        }
+        let (from, to) = (self.sample_rate, sample_rate);
+        let samples = |count| rescale(count, from, to);
+        let shift = |shift| rescale_shift(shift, from, to).clamp(1, MAX_SHIFT);
+        let config = ChannelConfig {
+            sample_rate,
+            warmup: samples(self.warmup),
+            debounce: samples(self.debounce),
+            tap_min: samples(self.tap_min),
+            long_threshold: samples(self.long_threshold),
+            hold_repeat: samples(self.hold_repeat),
+            baseline_shift: shift(self.baseline_shift),
+            delta_decay_shift: shift(self.delta_decay_shift),
+            max_hold: samples(self.max_hold),
+            filters: self.filters.map(|stage| stage.map(|stage| stage.at_sample_rate(from, to))),
+            fault_samples: samples(self.fault_samples),
+            ..*self
+        };
+        ChannelConfigBuilder {
+            config,
+            filter_count: self.filters.iter().flatten().count(),
+        }
+        .build()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ConfigError {
+    /// `warmup` must be at least one sample.
+    ZeroWarmup,
+    /// `touch_threshold` must be strictly between 0 and `LEVEL_ONE`.
+    TouchThresholdOutOfRange,
+    /// `tap_min` must be below `debounce`.
+    TapMinNotBelowDebounce,
+    /// `debounce` must not exceed `long_threshold`.
+    DebounceAboveLongThreshold,
+    /// `hold_repeat` must be at least one sample.
+    ZeroHoldRepeat,
+    /// `baseline_shift` and `delta_decay_shift` must be in `1..=MAX_SHIFT`.
+    ShiftOutOfRange,
+    /// `max_hold` must be above `long_threshold`.
+    MaxHoldNotAboveLongThreshold,
+    /// A filter stage's parameter is outside its range.
+    InvalidFilter,
+    /// More than `MAX_STAGES` filter stages were added.
+    TooManyFilters,
+    /// `proximity_threshold` must be between `touch_threshold` and
+    /// `LEVEL_ONE`, exclusive.
+    ProximityThresholdOutOfRange,
+    /// `fault_cycles` must have its minimum below its maximum.
+    FaultCyclesOutOfOrder,
+    /// `fault_samples` must be at least one sample.
+    ZeroFaultSamples,
+    /// `sample_rate` must be at least one sample a second.
+    ZeroSampleRate,
+    /// `tap_min` must be below `long_threshold`.
+    TapMinNotBelowLongThreshold,
+    /// `min_window` must be below `MAX_TIMEOUT`: a touch can't move the
+    /// count by more cycles than a measurement has time for.
+    MinWindowOutOfRange,
+    /// Durations, including the multi-tap window, must not exceed
+    /// `MAX_DURATION_S`.
+    DurationTooLong,
+}
+
+/// Longest filter time constant, as a power of two samples.
+pub const MAX_SHIFT: u32 = 16;
+/// Longest duration a config may set, in seconds. Keeps every sample count
+/// within a `u32` when rescaled to the fastest auto-ranged rate.
+pub const MAX_DURATION_S: u32 = 3600;
+/// Fractional bits kept by the baseline and delta trackers.
+const TRACK_FRAC_BITS: u32 = 8;
+/// Time constant (power of two) for tracking the baseline downwards, and in
+/// both directions during warmup.
+const FAST_SHIFT: u32 = 2;
+
+/// Builds a validated `ChannelConfig`, starting from the defaults.
+#[derive(Clone, Copy, Debug)]
+pub struct ChannelConfigBuilder {
+    config: ChannelConfig,
+    filter_count: usize,
+}
+
+impl ChannelConfigBuilder {
+    /// Samples per second the durations given to the builder are counted
+    /// at. Defaults to `DEFAULT_SAMPLE_RATE`, the rate at `touch.pio`'s
+    /// default timeout; see `ChannelConfig::at_sample_rate`.
+    pub fn sample_rate(mut self, samples_per_s: u32) -> Self {
+        self.config.sample_rate = samples_per_s;
+        self
+    }
+
+    /// Samples used to learn the pad's range before reporting anything.
+    pub fn warmup(mut self, samples: u32) -> Self {
+        self.config.warmup = samples;
+        self
+    }
+
+    /// Raw count spread below which the pad is treated as untouched noise.
+    pub fn min_window(mut self, counts: u32) -> Self {
+        self.config.min_window = counts;
+        self
+    }
+
+    /// Touch length before the long-press logic takes over.
+    pub fn debounce(mut self, samples: u32) -> Self {
+        self.config.debounce = samples;
+        self
+    }
+
+    /// Normalised level below which the pad counts as touched, in
+    /// `LEVEL_ONE` units.
+    pub fn touch_threshold(mut self, level: u32) -> Self {
+        self.config.touch_threshold = level;
+        self
+    }
+
+    /// Touches this short or shorter are ignored as noise.
+    pub fn tap_min(mut self, samples: u32) -> Self {
+        self.config.tap_min = samples;
+        self
+    }
+
+    pub fn long_threshold(mut self, samples: u32) -> Self {
+        self.config.long_threshold = samples;
+        self
+    }
+
+    pub fn hold_repeat(mut self, samples: u32) -> Self {
+        self.config.hold_repeat = samples;
+        self
+    }
+
+    /// Milliseconds to wait after a tap for another one before reporting the
+    /// gesture. Zero disables multi-tap: every tap is a `SingleTap` at once.
+    pub fn multi_tap_window_ms(mut self, ms: u32) -> Self {
+        self.config.multi_tap_window_ms = ms;
+        self
+    }
+
+    /// Untouched baseline follows upward drift with a time constant of
+    /// `2^shift` samples. It follows downward drift faster, and is frozen
+    /// while touched.
+    pub fn baseline_shift(mut self, shift: u32) -> Self {
+        self.config.baseline_shift = shift;
+        self
+    }
+
+    /// The touch-delta estimate jumps to each new peak and decays with a time
+    /// constant of `2^shift` samples, so a single outlier is soon forgotten.
+    pub fn delta_decay_shift(mut self, shift: u32) -> Self {
+        self.config.delta_decay_shift = shift;
+        self
+    }
+
+    /// Touches held longer than this are reported as `Stuck` and the pad is
+    /// recalibrated around whatever is resting on it.
+    pub fn max_hold(mut self, samples: u32) -> Self {
+        self.config.max_hold = samples;
+        self
+    }
+
+    /// Append a filter stage. Raw samples pass through the stages in the
+    /// order they were added before being classified.
+    pub fn filter(mut self, stage: FilterStage) -> Self {
+        if let Some(slot) = self.config.filters.get_mut(self.filter_count) {
+            *slot = Some(stage);
+        }
+        self.filter_count += 1;
+        self
+    }
+
+    /// Report `Approach`/`Leave` when the level crosses `level` (in
+    /// `LEVEL_ONE` units), which sits between untouched and the touch
+    /// threshold. Off unless set.
+    pub fn proximity_threshold(mut self, level: u32) -> Self {
+        self.config.proximity_threshold = Some(level);
+        self
+    }
+
+    /// Cycles per measurement outside `min..=max` are faults: fewer means the
+    /// pad is `Saturated`, more that it is `Open`. Off unless set, since it
+    /// depends on the measurement timeout.
+    pub fn fault_cycles(mut self, min: u32, max: u32) -> Self {
+        self.config.fault_cycles = Some((min, max));
+        self
+    }
+
+    /// Faulty (or healthy again) readings in a row before a fault (or the
+    /// recovery) is reported.
+    pub fn fault_samples(mut self, samples: u32) -> Self {
+        self.config.fault_samples = samples;
+        self
+    }
+
+    pub fn build(self) -> Result<ChannelConfig, ConfigError> {
+        let c = self.config;
+        if c.sample_rate == 0 {
+            return Err(ConfigError::ZeroSampleRate);
+        }
+        if c.warmup == 0 {
+            return Err(ConfigError::ZeroWarmup);
+        }
+        if c.touch_threshold == 0 || c.touch_threshold >= LEVEL_ONE {
+            return Err(ConfigError::TouchThresholdOutOfRange);
+        }
+        if c.min_window >= MAX_TIMEOUT {
+            return Err(ConfigError::MinWindowOutOfRange);
+        }
+        if c.tap_min >= c.long_threshold {
+            return Err(ConfigError::TapMinNotBelowLongThreshold);
+        }
+        if c.tap_min >= c.debounce {
+            return Err(ConfigError::TapMinNotBelowDebounce);
+        }
+        if c.debounce > c.long_threshold {
+            return Err(ConfigError::DebounceAboveLongThreshold);
+        }
+        if c.hold_repeat == 0 {
+            return Err(ConfigError::ZeroHoldRepeat);
+        }
+        let shift_range = 1..=MAX_SHIFT;
+        if !shift_range.contains(&c.baseline_shift) || !shift_range.contains(&c.delta_decay_shift) {
+            return Err(ConfigError::ShiftOutOfRange);
+        }
+        if c.max_hold <= c.long_threshold {
+            return Err(ConfigError::MaxHoldNotAboveLongThreshold);
+        }
+        if self.filter_count > MAX_STAGES {
+            return Err(ConfigError::TooManyFilters);
+        }
+        if !c.filters.iter().flatten().all(|stage| stage.is_valid()) {
+            return Err(ConfigError::InvalidFilter);
+        }
+        if let Some(level) = c.proximity_threshold {
+            if level <= c.touch_threshold || level >= LEVEL_ONE {
+                return Err(ConfigError::ProximityThresholdOutOfRange);
+            }
+        }
+        if c.fault_cycles.is_some_and(|(min, max)| min >= max) {
+            return Err(ConfigError::FaultCyclesOutOfOrder);
+        }
+        if c.fault_samples == 0 {
+            return Err(ConfigError::ZeroFaultSamples);
+        }
+        let max_samples = MAX_DURATION_S as u64 * c.sample_rate as u64;
+        let durations = [c.warmup, c.debounce, c.long_threshold, c.hold_repeat, c.max_hold, c.fault_samples];
+        if durations.iter().any(|&samples| samples as u64 > max_samples)
+            || c.multi_tap_window_ms as u64 > MAX_DURATION_S as u64 * 1000
+        {
+            return Err(ConfigError::DurationTooLong);
+        }
+        Ok(c)
+    }
 }
 
 pub struct Channel {
+    config: ChannelConfig,
+    filter: FilterChain,
     warmup: u32,
-    level_lo: u32,
-    level_hi: u32,
-    level: f32,
+    /// Untouched raw level, with `TRACK_FRAC_BITS` fractional bits.
+    baseline: Option<u64>,
+    /// Peak-hold estimate of how far a touch moves the raw value, with
+    /// `TRACK_FRAC_BITS` fractional bits.
+    delta: u64,
+    /// Last normalised level, in `LEVEL_ONE` units.
+    level: u32,
     last_state: bool,
     last_touch_state: TouchState,
     counter: u32,
+    pressed: bool,
+    long_press: bool,
+    hold_count: u32,
+    taps: u8,
+    tap_gap: u32,
+    /// `multi_tap_window_ms` in samples.
+    tap_window: u32,
+    near: bool,
+    near_count: u32,
+    fault: Option<SensorFault>,
+    /// Classification of the current run of readings that disagree with
+    /// `fault`, and its length.
+    fault_candidate: Option<SensorFault>,
+    fault_run: u32,
+}
+
+/// `1 - above / window` in `LEVEL_ONE` units, rounded down. Integer only:
+/// the M0+ has no FPU.
+fn level(above: u32, window: u32) -> u32 {
+    let below = window - above.min(window);
+    ((below as u64 * LEVEL_ONE as u64) / window as u64) as u32
 }
 
 impl Default for Channel {
     fn default() -> Self {
+        Self::new(ChannelConfig::default())
+    }
+}
+
+impl Channel {
+    pub fn new(config: ChannelConfig) -> Self {
         Channel {
-            warmup: 100,
-            level_lo: u32::MAX,
-            level_hi: 0,
-            level: 0.0,
+            config,
+            filter: FilterChain::new(&config.filters),
+            warmup: config.warmup,
+            baseline: None,
+            delta: 0,
+            level: LEVEL_ONE,
             last_state: false,
             last_touch_state: TouchState::Idle,
             counter: 0,
+            pressed: false,
+            long_press: false,
+            hold_count: 0,
+            taps: 0,
+            tap_gap: 0,
+            tap_window: rescale(config.multi_tap_window_ms, 1000, config.sample_rate),
+            near: false,
+            near_count: 0,
+            fault: None,
+            fault_candidate: None,
+            fault_run: 0,
         }
     }
-}
 
-const LONG_THRESHOLD: u32 = 300;
+    pub fn config(&self) -> &ChannelConfig {
+        &self.config
+    }
 
-impl Channel {
-    pub fn new() -> Self {
-        Self::default()
+    /// The fault last reported with `TouchEvent::Fault`, until the pad
+    /// recovers.
+    pub fn fault(&self) -> Option<SensorFault> {
+        self.fault
     }
 
-    fn normalize(&mut self, raw_val: u32) -> Option<f32> {
-        self.level_lo = self.level_lo.min(raw_val);
-        self.level_hi = self.level_hi.max(raw_val);
+    /// Last normalised level in `LEVEL_ONE` units: `LEVEL_ONE` untouched, 0
+    /// at a full touch. Reads as untouched until the pad is calibrated.
+    pub fn level(&self) -> u32 {
+        self.level
+    }
+
+    /// Level relative to the tracked baseline: `LEVEL_ONE` untouched, 0 at
+    /// the full touch delta. `None` until the delta clears `min_window`.
+    fn normalize(&mut self, raw_val: u32) -> Option<u32> {
+        let raw = (raw_val as u64) << TRACK_FRAC_BITS;
+        let baseline = *self.baseline.get_or_insert(raw);
+
+        self.delta -= self.delta >> self.config.delta_decay_shift;
+        self.delta = self.delta.max(raw.saturating_sub(baseline));
 
-        let window = self.level_hi - self.level_lo;
-        if window > 24 {
-            self.level = 1.0 - (raw_val - self.level_lo) as f32 / window as f32;
-            Some(self.level)
+        let window = (self.delta >> TRACK_FRAC_BITS) as u32;
+        let level = if window > self.config.min_window {
+            let above = (raw.saturating_sub(baseline) >> TRACK_FRAC_BITS) as u32;
+            Some(level(above, window))
         } else {
             None
+        };
+
+        let touched = level.is_some_and(|lvl| lvl < self.config.touch_threshold);
+        if !touched {
+            self.track_baseline(raw);
+        }
+        if let Some(lvl) = level {
+            self.level = lvl;
         }
+        level
+    }
+
+    fn track_baseline(&mut self, raw: u64) {
+        let Some(baseline) = self.baseline.as_mut() else {
+            return;
+        };
+        let shift = if self.warmup > 0 || raw < *baseline {
+            FAST_SHIFT
+        } else {
+            self.config.baseline_shift
+        };
+        let error = raw as i64 - *baseline as i64;
+        *baseline = (*baseline as i64 + (error >> shift)) as u64;
     }
 
     pub fn state(&mut self, raw_val: u32) -> TouchState {
+        let raw_val = self.filter.apply(raw_val);
+        if !self.filter.is_settled() {
+            // Partly filled windows would seed the baseline off one phase of
+            // the noise; warmup starts with the first full filter output
+            return TouchState::Warmup;
+        }
         if self.warmup > 0 {
             self.normalize(raw_val);
             self.warmup -= 1;
@@ -58,16 +559,21 @@ impl Channel {
         }
 
         let level = self.normalize(raw_val);
+        let touched = level.is_some_and(|lvl| lvl < self.config.touch_threshold);
+        if touched && self.counter >= self.config.max_hold {
+            self.recalibrate(raw_val);
+            return TouchState::Stuck;
+        }
         let new_state;
 
         match level {
             Some(lvl) => {
-                if self.counter > 200 {
-                    match lvl < 0.5 {
+                if self.counter > self.config.debounce {
+                    match lvl < self.config.touch_threshold {
                         true => {
                             match self.last_state {
                                 true => {
-                                    new_state = match self.counter > LONG_THRESHOLD {
+                                    new_state = match self.counter > self.config.long_threshold {
                                         true => TouchState::Long,
                                         false => TouchState::Idle,
                                     }
@@ -82,7 +588,7 @@ impl Channel {
                         false => {
                             match self.last_state {
                                 true => {
-                                    match self.counter != 0 && self.counter <= LONG_THRESHOLD {
+                                    match self.counter != 0 && self.counter <= self.config.long_threshold {
                                         true => new_state = TouchState::Short,
                                         false => new_state = TouchState::Idle,
                                     }
@@ -97,7 +603,7 @@ impl Channel {
                         }
                     }
                 } else {
-                    match lvl < 0.5 {
+                    match lvl < self.config.touch_threshold {
                         true => {
                             self.last_state = true;
                             self.count();
@@ -105,7 +611,7 @@ impl Channel {
                         false => {
                             if self.last_state {
                                 // Brief tap: release before main branch
-                                new_state = match self.counter > 5 {
+                                new_state = match self.counter > self.config.tap_min {
                                     true => TouchState::Short,
                                     false => TouchState::Idle,
                                 };
@@ -118,7 +624,9 @@ impl Channel {
                         }
                     }
                     new_state = match self.last_touch_state {
-                        TouchState::Short | TouchState::Long | TouchState::Warmup => TouchState::Idle,
+                        TouchState::Short | TouchState::Long | TouchState::Warmup | TouchState::Stuck => {
+                            TouchState::Idle
+                        }
                         state => state,
                     };
                 }
@@ -131,6 +639,174 @@ impl Channel {
         new_state
     }
 
+    /// Treat `raw_val` as untouched from now on. A later drop below it (the
+    /// object being lifted off) is followed quickly by the baseline.
+    fn recalibrate(&mut self, raw_val: u32) {
+        self.baseline = Some((raw_val as u64) << TRACK_FRAC_BITS);
+        self.level = LEVEL_ONE;
+        self.last_state = false;
+        self.counter = 0;
+        self.last_touch_state = TouchState::Stuck;
+    }
+
+    /// Feed one raw sample and return the events it produced.
+    pub fn events(&mut self, raw_val: u32) -> Events {
+        let mut events = Events::default();
+        let class = self.classify(raw_val);
+        let switched = self.check_fault(class, &mut events);
+        // A timed-out reading is not a count at all, so never classify it
+        if switched || self.fault.is_some() || class == Some(SensorFault::Shorted) {
+            return events;
+        }
+
+        let was_touching = self.last_state;
+        let state = self.state(raw_val);
+
+        if state == TouchState::Stuck {
+            self.pressed = false;
+            self.long_press = false;
+            events.push(TouchEvent::Stuck);
+            return events;
+        }
+        if state != TouchState::Warmup {
+            self.proximity(&mut events);
+        }
+
+        if self.last_state && !self.pressed && self.counter > self.config.tap_min {
+            self.pressed = true;
+            events.push(TouchEvent::Press);
+        }
+        if state == TouchState::Long {
+            if self.long_press {
+                self.hold_count += 1;
+                if self.hold_count >= self.config.hold_repeat {
+                    self.hold_count = 0;
+                    events.push(TouchEvent::HoldRepeat);
+                }
+            } else {
+                // A hold ends any tap sequence in progress. The taps are
+                // dropped rather than reported: acting on them in the same
+                // sample as the hold would fight it (a toggle on, say,
+                // undone at once by the hold's toggle off)
+                self.taps = 0;
+                self.long_press = true;
+                self.hold_count = 0;
+                events.push(TouchEvent::LongPressStart);
+            }
+        }
+        if was_touching && !self.last_state && self.pressed {
+            self.pressed = false;
+            events.push(TouchEvent::Release);
+            if state == TouchState::Short {
+                events.push(TouchEvent::Tap);
+                self.tap(&mut events);
+            } else if self.long_press {
+                events.push(TouchEvent::LongPressEnd);
+            }
+            self.long_press = false;
+        } else if self.taps > 0 && !self.pressed {
+            self.tap_gap += 1;
+            if self.tap_gap >= self.tap_window {
+                self.resolve_taps(&mut events);
+            }
+        }
+        events
+    }
+
+    /// Report that no sample has arrived for too long. Returns
+    /// `Fault(NoSamples)` the first time; the pad recovers once healthy
+    /// samples arrive again.
+    pub fn stalled(&mut self) -> Events {
+        let mut events = Events::default();
+        if self.fault != Some(SensorFault::NoSamples) {
+            self.restart();
+            self.fault = Some(SensorFault::NoSamples);
+            events.push(TouchEvent::Fault(SensorFault::NoSamples));
+        }
+        events
+    }
+
+    fn classify(&self, raw_val: u32) -> Option<SensorFault> {
+        let cycles = match Reading::from_raw(raw_val) {
+            Reading::Timeout => return Some(SensorFault::Shorted),
+            reading => reading.cycles(),
+        };
+        let (min, max) = self.config.fault_cycles?;
+        if cycles < min {
+            Some(SensorFault::Saturated)
+        } else if cycles > max {
+            Some(SensorFault::Open)
+        } else {
+            None
+        }
+    }
+
+    /// Switch to the classification of the current run of readings once it
+    /// has lasted `fault_samples`, returning whether it did. Either way the
+    /// pad starts over: a touch in progress is dropped, and a recovered pad
+    /// warms up again from the next sample.
+    fn check_fault(&mut self, class: Option<SensorFault>, events: &mut Events) -> bool {
+        if class == self.fault {
+            self.fault_run = 0;
+            return false;
+        }
+        if class != self.fault_candidate {
+            self.fault_candidate = class;
+            self.fault_run = 0;
+        }
+        self.fault_run += 1;
+        if self.fault_run < self.config.fault_samples {
+            return false;
+        }
+        self.restart();
+        self.fault = class;
+        events.push(match class {
+            Some(fault) => TouchEvent::Fault(fault),
+            None => TouchEvent::Recovered,
+        });
+        true
+    }
+
+    fn restart(&mut self) {
+        *self = Channel::new(self.config);
+    }
+
+    /// Track the level against the proximity threshold, switching only
+    /// after more than `tap_min` samples on the other side.
+    fn proximity(&mut self, events: &mut Events) {
+        let Some(threshold) = self.config.proximity_threshold else {
+            return;
+        };
+        if (self.level < threshold) == self.near {
+            self.near_count = 0;
+            return;
+        }
+        self.near_count += 1;
+        if self.near_count > self.config.tap_min {
+            self.near = !self.near;
+            self.near_count = 0;
+            events.push(if self.near { TouchEvent::Approach } else { TouchEvent::Leave });
+        }
+    }
+
+    fn tap(&mut self, events: &mut Events) {
+        self.taps += 1;
+        self.tap_gap = 0;
+        if self.taps >= 3 || self.tap_window == 0 {
+            self.resolve_taps(events);
+        }
+    }
+
+    fn resolve_taps(&mut self, events: &mut Events) {
+        match self.taps {
+            0 => (),
+            1 => events.push(TouchEvent::SingleTap),
+            2 => events.push(TouchEvent::DoubleTap),
+            _ => events.push(TouchEvent::TripleTap),
+        }
+        self.taps = 0;
+    }
+
     fn count(&mut self) {
         self.counter = self.counter.saturating_add(1);
     }
@@ -144,7 +820,7 @@ mod tests {
 
     #[test]
     fn test_warmup_returns_warmup_for_100_calls() {
-        let mut ch = Channel::new();
+        let mut ch = Channel::default();
         for _ in 0..100 {
             assert_eq!(ch.state(1000), TouchState::Warmup);
         }
@@ -152,7 +828,7 @@ mod tests {
 
     #[test]
     fn test_after_warmup_state_is_idle_when_window_small() {
-        let mut ch = Channel::new();
+        let mut ch = Channel::default();
         for _ in 0..101 {
             ch.state(1000);
         }
@@ -163,7 +839,7 @@ mod tests {
 
     #[test]
     fn test_state_is_idle_when_window_below_25() {
-        let mut ch = Channel::new();
+        let mut ch = Channel::default();
         for _ in 0..100 {
             ch.state(1000);
         }
@@ -176,7 +852,7 @@ mod tests {
 
     #[test]
     fn test_state_returns_idle_after_window_exceeds_24() {
-        let mut ch = Channel::new();
+        let mut ch = Channel::default();
         for _ in 0..100 {
             ch.state(1000);
         }
@@ -191,7 +867,7 @@ mod tests {
 
     #[test]
     fn test_short_touch_detected_on_release() {
-        let mut ch = Channel::new();
+        let mut ch = Channel::default();
         for _ in 0..100 {
             ch.state(1000);
         }
@@ -208,7 +884,7 @@ mod tests {
 
     #[test]
     fn test_short_touch_one_frame_is_idle() {
-        let mut ch = Channel::new();
+        let mut ch = Channel::default();
         for _ in 0..100 {
             ch.state(1000);
         }
@@ -220,7 +896,7 @@ mod tests {
 
     #[test]
     fn test_short_touch_brief_is_short() {
-        let mut ch = Channel::new();
+        let mut ch = Channel::default();
         for _ in 0..100 {
             ch.state(1000);
         }
@@ -236,7 +912,7 @@ mod tests {
 
     #[test]
     fn test_long_touch_detected_after_300_samples() {
-        let mut ch = Channel::new();
+        let mut ch = Channel::default();
         for _ in 0..100 {
             ch.state(1000);
         }
@@ -250,7 +926,7 @@ mod tests {
 
     #[test]
     fn test_long_touch_releases_to_idle_not_short() {
-        let mut ch = Channel::new();
+        let mut ch = Channel::default();
         for _ in 0..100 {
             ch.state(1000);
         }
@@ -266,7 +942,7 @@ mod tests {
 
     #[test]
     fn test_long_touch_releases_and_then_short_on_next_tap() {
-        let mut ch = Channel::new();
+        let mut ch = Channel::default();
         for _ in 0..100 {
             ch.state(1000);
         }
@@ -290,7 +966,7 @@ mod tests {
 
     #[test]
     fn test_normalize_level_is_0_at_max_touch() {
-        let mut ch = Channel::new();
+        let mut ch = Channel::default();
         for _ in 0..100 {
             ch.state(1000);
         }
@@ -309,7 +985,7 @@ mod tests {
 
     #[test]
     fn test_normalize_level_is_1_at_baseline_min() {
-        let mut ch = Channel::new();
+        let mut ch = Channel::default();
         for _ in 0..100 {
             ch.state(1000);
         }
@@ -322,4 +998,1025 @@ mod tests {
         // level >= 0.5 → not-touching branch
         assert_eq!(s, TouchState::Idle);
     }
+
+    // --- Fixed-point level ---
+
+    /// The original floating-point normalisation, kept as a reference.
+    fn level_f32(above: u32, window: u32) -> f32 {
+        1.0 - above.min(window) as f32 / window as f32
+    }
+
+    fn threshold_f32(config: &ChannelConfig) -> f32 {
+        config.touch_threshold as f32 / LEVEL_ONE as f32
+    }
+
+    /// Raw sample streams used by the tests in this file.
+    fn vectors() -> Vec<Vec<u32>> {
+        let run = |raw: u32, n: usize| std::iter::repeat_n(raw, n);
+        let warm = || run(1000, 100);
+        vec![
+            warm().chain(run(1100, 50)).chain(run(1000, 50)).collect(),
+            warm().chain(run(1100, 400)).chain(run(1000, 10)).chain(run(1100, 250)).collect(),
+            warm().chain((0..1000).map(|i| if i % 2 == 0 { 1010 } else { 1005 })).collect(),
+            warm().chain(run(1100, 1)).chain(run(1000, 100)).chain(run(6000, 1)).chain(run(1000, 3000)).collect(),
+            warm().chain(run(1100, 9100)).chain(run(1200, 50)).chain(run(1000, 200)).chain(run(1100, 50)).collect(),
+            warm().chain(run(1300, 9100)).chain(run(1000, 3000)).chain(run(1100, 50)).collect(),
+            warm()
+                .chain((0..20_000).map(|i| 1000 + i / 50 + if i % 2000 < 50 { 100 } else { 0 }))
+                .collect(),
+            run(1000, 200).chain(run(1000 + 4 * 60 + 100, 600)).chain(run(1000, 50)).collect(),
+        ]
+    }
+
+    /// The channel only uses the level through these comparisons, so
+    /// deciding them as the f32 code did on every sample means producing
+    /// the same events.
+    #[test]
+    fn test_fixed_point_matches_float_on_test_vectors() {
+        let clean = tap_waveform();
+        let noisy: Vec<Vec<u32>> = (1..=4)
+            .flat_map(|seed| [white(&clean, seed), emi(&clean, seed), combined(&clean, seed)])
+            .map(|raws| raws.into_iter().map(|v| v.max(0) as u32).collect())
+            .collect();
+        let proximity = ChannelConfig::builder().proximity_threshold(LEVEL_ONE * 4 / 5).build().unwrap();
+        let multi_tap = ChannelConfig::builder().multi_tap_window_ms(300).build().unwrap();
+        let mut configs = configs();
+        configs.extend([proximity, multi_tap]);
+        let mut compared = 0;
+        for config in configs {
+            let near = config.proximity_threshold.map(|level| level as f32 / LEVEL_ONE as f32);
+            for raws in vectors().iter().chain(&noisy) {
+                let mut ch = Channel::new(config);
+                for &raw in raws {
+                    // Mirror the integer trackers to see the inputs normalize() uses
+                    let raw_q = (raw as u64) << TRACK_FRAC_BITS;
+                    let baseline = ch.baseline.unwrap_or(raw_q);
+                    let delta = ch.delta - (ch.delta >> config.delta_decay_shift);
+                    let window = (delta.max(raw_q.saturating_sub(baseline)) >> TRACK_FRAC_BITS) as u32;
+                    let above = (raw_q.saturating_sub(baseline) >> TRACK_FRAC_BITS) as u32;
+
+                    if ch.state(raw) == TouchState::Stuck || window <= config.min_window {
+                        continue;
+                    }
+                    let fixed = ch.level;
+                    let float = level_f32(above, window);
+                    assert_eq!(fixed < config.touch_threshold, float < threshold_f32(&config));
+                    if let (Some(fixed_near), Some(near)) = (config.proximity_threshold, near) {
+                        assert_eq!(fixed < fixed_near, float < near, "above {} window {}", above, window);
+                    }
+                    assert!((fixed as f32 / LEVEL_ONE as f32 - float).abs() <= 1.0 / LEVEL_ONE as f32);
+                    compared += 1;
+                }
+            }
+        }
+        assert!(compared > 100_000, "{} samples", compared);
+    }
+
+    #[test]
+    fn test_fixed_point_matches_float_exhaustively() {
+        // Every window the test vectors can produce, and then some
+        for config in configs() {
+            for window in config.min_window + 1..=6000 {
+                for above in (0..=window + 1).step_by(1 + window as usize / 1000) {
+                    let fixed = level(above, window);
+                    let float = level_f32(above, window);
+                    assert_eq!(
+                        fixed < config.touch_threshold,
+                        float < threshold_f32(&config),
+                        "above {} window {}",
+                        above,
+                        window
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_fixed_point_level_end_points() {
+        assert_eq!(level(0, 100), LEVEL_ONE);
+        assert_eq!(level(100, 100), 0);
+        assert_eq!(level(500, 100), 0);
+        assert_eq!(level(50, 100), LEVEL_ONE / 2);
+    }
+
+    // --- Events ---
+
+    fn warm_channel() -> Channel {
+        let mut ch = Channel::default();
+        for _ in 0..100 {
+            assert_eq!(ch.events(1000).count(), 0);
+        }
+        ch
+    }
+
+    /// Feed `n` copies of `raw` and collect every event produced.
+    fn feed(ch: &mut Channel, raw: u32, n: usize) -> Vec<TouchEvent> {
+        (0..n).flat_map(|_| ch.events(raw)).collect()
+    }
+
+    #[test]
+    fn test_events_tap_sequence() {
+        let mut ch = warm_channel();
+        assert_eq!(feed(&mut ch, 1100, 50), [TouchEvent::Press]);
+        assert_eq!(
+            feed(&mut ch, 1000, 1),
+            [TouchEvent::Release, TouchEvent::Tap, TouchEvent::SingleTap]
+        );
+        assert!(feed(&mut ch, 1000, 50).is_empty());
+    }
+
+    #[test]
+    fn test_events_press_after_tap_minimum() {
+        let mut ch = warm_channel();
+        assert!(feed(&mut ch, 1100, 5).is_empty());
+        assert_eq!(feed(&mut ch, 1100, 1), [TouchEvent::Press]);
+    }
+
+    #[test]
+    fn test_events_tap_after_debounce() {
+        let mut ch = warm_channel();
+        assert_eq!(feed(&mut ch, 1100, 250), [TouchEvent::Press]);
+        assert_eq!(
+            feed(&mut ch, 1000, 1),
+            [TouchEvent::Release, TouchEvent::Tap, TouchEvent::SingleTap]
+        );
+    }
+
+    #[test]
+    fn test_events_noise_blip_is_silent() {
+        let mut ch = warm_channel();
+        assert!(feed(&mut ch, 1100, 3).is_empty());
+        assert!(feed(&mut ch, 1000, 10).is_empty());
+    }
+
+    #[test]
+    fn test_events_long_press_sequence() {
+        let mut ch = warm_channel();
+        assert_eq!(feed(&mut ch, 1100, 300), [TouchEvent::Press]);
+        assert_eq!(feed(&mut ch, 1100, 2), [TouchEvent::LongPressStart]);
+        assert!(feed(&mut ch, 1100, 99).is_empty());
+        assert_eq!(feed(&mut ch, 1100, 1), [TouchEvent::HoldRepeat]);
+        assert_eq!(
+            feed(&mut ch, 1100, 200),
+            [TouchEvent::HoldRepeat, TouchEvent::HoldRepeat]
+        );
+        assert_eq!(
+            feed(&mut ch, 1000, 1),
+            [TouchEvent::Release, TouchEvent::LongPressEnd]
+        );
+    }
+
+    #[test]
+    fn test_events_long_press_then_tap() {
+        let mut ch = warm_channel();
+        feed(&mut ch, 1100, 400);
+        feed(&mut ch, 1000, 1);
+        assert_eq!(feed(&mut ch, 1100, 250), [TouchEvent::Press]);
+        assert_eq!(
+            feed(&mut ch, 1000, 1),
+            [TouchEvent::Release, TouchEvent::Tap, TouchEvent::SingleTap]
+        );
+    }
+
+    #[test]
+    fn test_events_every_press_is_released() {
+        let mut ch = warm_channel();
+        for hold in [6, 50, 201, 299, 300, 301, 302, 450] {
+            let mut events = feed(&mut ch, 1100, hold);
+            events.extend(feed(&mut ch, 1000, 20));
+            let presses = events.iter().filter(|e| **e == TouchEvent::Press).count();
+            let releases = events.iter().filter(|e| **e == TouchEvent::Release).count();
+            assert_eq!((presses, releases), (1, 1), "hold: {} {:?}", hold, events);
+        }
+    }
+
+    // --- Multi-tap ---
+
+    const WINDOW_MS: u32 = 200;
+    /// `WINDOW_MS` in samples at `DEFAULT_SAMPLE_RATE`.
+    const WINDOW: u32 = 60;
+
+    fn multi_tap_channel() -> Channel {
+        let config = ChannelConfig::builder().multi_tap_window_ms(WINDOW_MS).build().unwrap();
+        let mut ch = Channel::new(config);
+        feed(&mut ch, 1000, 100);
+        ch
+    }
+
+    /// A 20-sample tap followed by `gap` untouched samples.
+    fn tap(ch: &mut Channel, gap: usize) -> Vec<TouchEvent> {
+        let mut events = feed(ch, 1100, 20);
+        events.extend(feed(ch, 1000, gap));
+        events.retain(|e| !matches!(e, TouchEvent::Press | TouchEvent::Release | TouchEvent::Tap));
+        events
+    }
+
+    #[test]
+    fn test_single_tap_reported_after_window() {
+        let mut ch = multi_tap_channel();
+        assert!(tap(&mut ch, WINDOW as usize).is_empty());
+        assert_eq!(feed(&mut ch, 1000, 1), [TouchEvent::SingleTap]);
+    }
+
+    #[test]
+    fn test_single_tap_delay_is_at_most_window() {
+        let mut ch = multi_tap_channel();
+        feed(&mut ch, 1100, 20);
+        let mut delay = 0;
+        loop {
+            let events = feed(&mut ch, 1000, 1);
+            if events.contains(&TouchEvent::SingleTap) {
+                break;
+            }
+            delay += 1;
+            assert!(delay <= WINDOW, "single tap not reported");
+        }
+    }
+
+    #[test]
+    fn test_double_tap() {
+        let mut ch = multi_tap_channel();
+        assert!(tap(&mut ch, 10).is_empty());
+        assert!(tap(&mut ch, 10).is_empty());
+        assert_eq!(feed(&mut ch, 1000, WINDOW as usize), [TouchEvent::DoubleTap]);
+    }
+
+    #[test]
+    fn test_triple_tap_reported_immediately() {
+        let mut ch = multi_tap_channel();
+        tap(&mut ch, 10);
+        tap(&mut ch, 10);
+        assert_eq!(tap(&mut ch, 1), [TouchEvent::TripleTap]);
+        assert!(feed(&mut ch, 1000, 2 * WINDOW as usize).is_empty());
+    }
+
+    #[test]
+    fn test_taps_outside_window_are_separate() {
+        let mut ch = multi_tap_channel();
+        assert_eq!(tap(&mut ch, WINDOW as usize + 1), [TouchEvent::SingleTap]);
+        assert_eq!(tap(&mut ch, WINDOW as usize + 1), [TouchEvent::SingleTap]);
+    }
+
+    #[test]
+    fn test_window_paused_while_finger_down() {
+        let mut ch = multi_tap_channel();
+        tap(&mut ch, 10);
+        // Second touch lasts longer than the window, but is still a tap
+        let mut events = feed(&mut ch, 1100, 250);
+        events.extend(feed(&mut ch, 1000, WINDOW as usize + 1));
+        assert_eq!(
+            events,
+            [TouchEvent::Press, TouchEvent::Release, TouchEvent::Tap, TouchEvent::DoubleTap]
+        );
+    }
+
+    #[test]
+    fn test_long_press_drops_pending_taps() {
+        for taps in 1..=2 {
+            let mut ch = multi_tap_channel();
+            for _ in 0..taps {
+                assert!(tap(&mut ch, 10).is_empty());
+            }
+            let events = feed(&mut ch, 1100, 302);
+            assert_eq!(events, [TouchEvent::Press, TouchEvent::LongPressStart], "{} taps", taps);
+            let events = feed(&mut ch, 1000, 2 * WINDOW as usize);
+            assert_eq!(events, [TouchEvent::Release, TouchEvent::LongPressEnd], "{} taps", taps);
+        }
+    }
+
+    #[test]
+    fn test_window_is_the_same_time_at_any_rate() {
+        for rate in [75, 300, 1200] {
+            let config = ChannelConfig::builder().multi_tap_window_ms(WINDOW_MS).build().unwrap();
+            let config = config.at_sample_rate(rate).unwrap();
+            let mut ch = Channel::new(config);
+            feed(&mut ch, 1000, config.warmup as usize);
+            feed(&mut ch, 1100, config.debounce as usize / 2);
+            while !ch.events(1000).any(|e| e == TouchEvent::Release) {}
+            let mut samples = 1;
+            while !ch.events(1000).any(|e| e == TouchEvent::SingleTap) {
+                samples += 1;
+            }
+            assert_eq!(samples, WINDOW_MS * rate / 1000, "{} samples/s", rate);
+        }
+    }
+
+    // --- Baseline drift ---
+
+    /// Count every tap and spurious press while `raw(i)` is fed for `n` samples.
+    fn count_taps(ch: &mut Channel, n: usize, raw: impl Fn(usize) -> u32) -> (usize, usize) {
+        let events: Vec<_> = (0..n).flat_map(|i| ch.events(raw(i))).collect();
+        let taps = events.iter().filter(|&&e| e == TouchEvent::SingleTap).count();
+        let presses = events.iter().filter(|&&e| e == TouchEvent::Press).count();
+        (taps, presses)
+    }
+
+    #[test]
+    fn test_drift_up_keeps_detecting_taps() {
+        // Baseline climbs 1 count every 50 samples, ten times the touch delta
+        // over the run; a 50-sample tap lands every 2000 samples.
+        let mut ch = warm_channel();
+        let (taps, presses) = count_taps(&mut ch, 50_000, |i| {
+            let drift = 1000 + i as u32 / 50;
+            let touching = i % 2000 >= 1000 && i % 2000 < 1050;
+            drift + if touching { 100 } else { 0 }
+        });
+        assert_eq!(taps, 25);
+        assert_eq!(presses, 25);
+    }
+
+    #[test]
+    fn test_drift_down_keeps_detecting_taps() {
+        let mut ch = Channel::default();
+        let (taps, presses) = count_taps(&mut ch, 50_000, |i| {
+            let drift = 2000 - i as u32 / 50;
+            let touching = i % 2000 >= 1000 && i % 2000 < 1050;
+            drift + if touching { 100 } else { 0 }
+        });
+        assert_eq!(taps, 25);
+        assert_eq!(presses, 25);
+    }
+
+    #[test]
+    fn test_drift_alone_is_silent() {
+        let mut ch = warm_channel();
+        let (taps, presses) = count_taps(&mut ch, 50_000, |i| 1000 + i as u32 / 50);
+        assert_eq!((taps, presses), (0, 0));
+    }
+
+    #[test]
+    fn test_outlier_is_forgotten() {
+        let mut ch = warm_channel();
+        feed(&mut ch, 1100, 50);
+        feed(&mut ch, 1000, 100);
+        // One wild sample inflates the touch delta far past a real touch
+        feed(&mut ch, 6000, 1);
+        feed(&mut ch, 1000, 1);
+        assert!(feed(&mut ch, 1100, 50).is_empty());
+        feed(&mut ch, 1000, 3000);
+
+        assert_eq!(feed(&mut ch, 1100, 50), [TouchEvent::Press]);
+        assert_eq!(
+            feed(&mut ch, 1000, 1),
+            [TouchEvent::Release, TouchEvent::Tap, TouchEvent::SingleTap]
+        );
+    }
+
+    #[test]
+    fn test_baseline_frozen_while_touched() {
+        let mut ch = warm_channel();
+        feed(&mut ch, 1100, 2000);
+        assert_eq!(ch.state(1100), TouchState::Long);
+        assert_eq!(ch.state(1000), TouchState::Idle);
+        assert_eq!(ch.level, LEVEL_ONE);
+    }
+
+    // --- Stuck touch ---
+
+    const MAX_HOLD: usize = 9000;
+
+    /// Rest an object on the pad until it is reported stuck.
+    fn place_object(ch: &mut Channel, raw: u32) {
+        let events = feed(ch, raw, MAX_HOLD + 2);
+        assert_eq!(events.first(), Some(&TouchEvent::Press));
+        assert_eq!(events[1], TouchEvent::LongPressStart);
+        assert_eq!(events.last(), Some(&TouchEvent::Stuck));
+        assert!(!events.contains(&TouchEvent::Release));
+    }
+
+    fn assert_taps(ch: &mut Channel, idle: u32, touch: u32) {
+        assert_eq!(feed(ch, touch, 50), [TouchEvent::Press]);
+        assert_eq!(feed(ch, idle, 1), [TouchEvent::Release, TouchEvent::Tap, TouchEvent::SingleTap]);
+    }
+
+    #[test]
+    fn test_stuck_reported_once() {
+        let mut ch = warm_channel();
+        place_object(&mut ch, 1100);
+        assert!(feed(&mut ch, 1100, 3 * MAX_HOLD).is_empty());
+        assert_eq!(ch.state(1100), TouchState::Idle);
+    }
+
+    #[test]
+    fn test_touch_on_top_of_object() {
+        let mut ch = warm_channel();
+        place_object(&mut ch, 1100);
+        feed(&mut ch, 1100, 100);
+        assert_taps(&mut ch, 1100, 1200);
+    }
+
+    #[test]
+    fn test_object_removed_resumes_detection() {
+        let mut ch = warm_channel();
+        place_object(&mut ch, 1100);
+        feed(&mut ch, 1100, 500);
+        assert!(feed(&mut ch, 1000, 100).is_empty());
+        assert_taps(&mut ch, 1000, 1100);
+        assert_taps(&mut ch, 1000, 1100);
+    }
+
+    #[test]
+    fn test_heavy_object_removed_resumes_detection() {
+        // Object moves the pad three times as far as a finger; the touch
+        // delta has to decay back before a finger registers again.
+        let mut ch = warm_channel();
+        place_object(&mut ch, 1300);
+        assert!(feed(&mut ch, 1300, 500).is_empty());
+        assert!(feed(&mut ch, 1000, 3000).is_empty());
+        assert_taps(&mut ch, 1000, 1100);
+    }
+
+    #[test]
+    fn test_object_placed_and_removed_repeatedly() {
+        let mut ch = warm_channel();
+        for _ in 0..3 {
+            place_object(&mut ch, 1100);
+            assert!(feed(&mut ch, 1000, 100).is_empty());
+            assert_taps(&mut ch, 1000, 1100);
+            feed(&mut ch, 1000, 100);
+        }
+    }
+
+    #[test]
+    fn test_long_hold_below_max_is_not_stuck() {
+        let mut ch = warm_channel();
+        let events = feed(&mut ch, 1100, MAX_HOLD - 1);
+        assert!(!events.contains(&TouchEvent::Stuck));
+        assert_eq!(feed(&mut ch, 1000, 1), [TouchEvent::Release, TouchEvent::LongPressEnd]);
+    }
+
+    // --- Filtering ---
+
+    /// Deterministic xorshift32, so noisy runs are repeatable.
+    struct Noise(u32);
+
+    impl Noise {
+        fn next(&mut self) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0
+        }
+
+        /// Uniform in `-amplitude..=amplitude`.
+        fn uniform(&mut self, amplitude: i32) -> i32 {
+            (self.next() % (2 * amplitude as u32 + 1)) as i32 - amplitude
+        }
+
+        fn chance(&mut self, percent: u32) -> bool {
+            self.next() % 100 < percent
+        }
+    }
+
+    const TAP_PERIOD: usize = 1500;
+    const TAPS: usize = 20;
+
+    /// A clean recording of `TAPS` finger taps on a pad at rest at 1000
+    /// counts: each press ramps up by 100 over a few samples, holds for 60
+    /// samples and ramps back down.
+    fn tap_waveform() -> Vec<i32> {
+        const RAMP: [i32; 5] = [10, 35, 65, 90, 100];
+        (0..TAP_PERIOD * TAPS)
+            .map(|i| {
+                let t = i % TAP_PERIOD;
+                let touch = match t {
+                    0..=999 => 0,
+                    1000..=1004 => RAMP[t - 1000],
+                    1005..=1064 => 100,
+                    1065..=1069 => RAMP[1069 - t],
+                    _ => 0,
+                };
+                1000 + touch
+            })
+            .collect()
+    }
+
+    /// Mains hum: a 50 Hz triangle at ~300 samples/s, peak-to-peak `amplitude`.
+    fn hum(i: usize, amplitude: i32) -> i32 {
+        [0, 1, 2, 3, 2, 1][i % 6] * amplitude / 3 - amplitude / 2
+    }
+
+    /// Run `waveform` through a channel and count (taps, presses).
+    fn classify(config: ChannelConfig, waveform: impl Iterator<Item = i32>) -> (usize, usize) {
+        let samples: Vec<u32> = waveform.map(|v| v.max(0) as u32).collect();
+        let mut ch = Channel::new(config);
+        count_taps(&mut ch, samples.len(), |i| samples[i])
+    }
+
+    fn filtered(stages: &[FilterStage]) -> ChannelConfig {
+        stages
+            .iter()
+            .fold(ChannelConfig::builder(), |b, &stage| b.filter(stage))
+            .build()
+            .unwrap()
+    }
+
+    const SEEDS: u32 = 8;
+
+    fn unfiltered() -> ChannelConfig {
+        ChannelConfig::default()
+    }
+
+    /// Spike rejection, hum notch and smoothing, as used in the firmware.
+    fn firmware_filters() -> ChannelConfig {
+        filtered(&[FilterStage::Median(5), FilterStage::Notch(6), FilterStage::Ema(2)])
+    }
+
+    fn white(clean: &[i32], seed: u32) -> Vec<i32> {
+        let mut n = Noise(seed);
+        clean.iter().map(|v| v + n.uniform(15)).collect()
+    }
+
+    /// Hum of a different strength for each seed, all strong enough to hide
+    /// taps from an unfiltered channel.
+    fn hummed(clean: &[i32], seed: u32) -> Vec<i32> {
+        let amplitude = 180 + 20 * seed as i32;
+        clean.iter().enumerate().map(|(i, v)| v + hum(i, amplitude)).collect()
+    }
+
+    /// Single-sample +300 spikes on 1% of samples, like SPI bursts coupling
+    /// into the pad.
+    fn emi(clean: &[i32], seed: u32) -> Vec<i32> {
+        let mut n = Noise(seed);
+        clean.iter().map(|v| v + if n.chance(1) { 300 } else { 0 }).collect()
+    }
+
+    fn combined(clean: &[i32], seed: u32) -> Vec<i32> {
+        let mut n = Noise(seed);
+        clean
+            .iter()
+            .enumerate()
+            .map(|(i, v)| v + n.uniform(10) + hum(i, 60) + if n.chance(1) { 300 } else { 0 })
+            .collect()
+    }
+
+    /// Total (false triggers, missed taps) over `SEEDS` noisy runs of
+    /// `TAPS` taps each.
+    fn error_counts(config: ChannelConfig, noise: fn(&[i32], u32) -> Vec<i32>) -> (usize, usize) {
+        let clean = tap_waveform();
+        (1..=SEEDS)
+            .map(|seed| classify(config, noise(&clean, seed).into_iter()))
+            .fold((0, 0), |(false_triggers, missed), (taps, presses)| {
+                (false_triggers + presses.saturating_sub(TAPS), missed + TAPS.saturating_sub(taps))
+            })
+    }
+
+    #[test]
+    fn test_filters_pass_clean_taps() {
+        let clean = tap_waveform();
+        let configs = [
+            unfiltered(),
+            firmware_filters(),
+            filtered(&[FilterStage::Median(9)]),
+            filtered(&[FilterStage::Notch(6)]),
+            filtered(&[FilterStage::Ema(3)]),
+        ];
+        for config in configs {
+            assert_eq!(classify(config, clean.iter().copied()), (TAPS, TAPS), "{:?}", config);
+        }
+    }
+
+    // Each run is 30,000 samples, about 100 s; `SEEDS` runs are ~13 minutes.
+
+    #[test]
+    fn test_white_noise_false_trigger_rate() {
+        let (false_triggers, missed) = error_counts(unfiltered(), white);
+        assert!(false_triggers > 500, "{}", false_triggers);
+        assert_eq!(missed, 0);
+        assert_eq!(error_counts(firmware_filters(), white), (1, 0));
+    }
+
+    #[test]
+    fn test_warmup_waits_for_filters_to_settle() {
+        let config = filtered(&[FilterStage::Median(5), FilterStage::Notch(6)]);
+        let mut ch = Channel::new(config);
+        // 4 samples fill the median and 5 more the notch before warmup counts
+        for _ in 0..config.warmup + 9 {
+            assert_eq!(ch.state(1000), TouchState::Warmup);
+        }
+        assert_ne!(ch.state(1000), TouchState::Warmup);
+    }
+
+    #[test]
+    fn test_mains_hum_hides_taps() {
+        assert_eq!(error_counts(unfiltered(), hummed), (0, SEEDS as usize * TAPS));
+        assert_eq!(error_counts(filtered(&[FilterStage::Notch(6)]), hummed), (0, 0));
+        assert_eq!(error_counts(firmware_filters(), hummed), (0, 0));
+    }
+
+    #[test]
+    fn test_emi_spikes_inflate_touch_delta() {
+        // Unfiltered spikes stretch the touch delta until real taps no longer
+        // reach the threshold; a median drops them before they count.
+        let (_, missed) = error_counts(unfiltered(), emi);
+        assert!(missed > SEEDS as usize * TAPS / 2, "{}", missed);
+        assert_eq!(error_counts(filtered(&[FilterStage::Median(5)]), emi), (0, 0));
+        let (false_triggers, missed) = error_counts(firmware_filters(), emi);
+        assert!(false_triggers <= 4, "{}", false_triggers);
+        assert_eq!(missed, 0);
+    }
+
+    #[test]
+    fn test_combined_noise_false_trigger_rate() {
+        let (_, missed) = error_counts(unfiltered(), combined);
+        assert!(missed > SEEDS as usize * TAPS / 2, "{}", missed);
+        let (false_triggers, missed) = error_counts(firmware_filters(), combined);
+        assert!(false_triggers <= 4, "{}", false_triggers);
+        assert_eq!(missed, 0);
+    }
+
+    // --- Proximity ---
+
+    const NEAR: u32 = LEVEL_ONE * 4 / 5;
+
+    /// Warm channel with proximity detection that has seen one tap, so it
+    /// knows how far a touch moves the count.
+    fn proximity_channel() -> Channel {
+        let config = ChannelConfig::builder().proximity_threshold(NEAR).build().unwrap();
+        let mut ch = Channel::new(config);
+        feed(&mut ch, 1000, 100);
+        let events = feed(&mut ch, 1100, 50);
+        assert_eq!(events, [TouchEvent::Approach, TouchEvent::Press]);
+        feed(&mut ch, 1000, 50);
+        ch
+    }
+
+    #[test]
+    fn test_proximity_off_by_default() {
+        let mut ch = warm_channel();
+        feed(&mut ch, 1100, 50);
+        let events = feed(&mut ch, 1030, 50);
+        assert!(!events.contains(&TouchEvent::Approach));
+        assert!(!feed(&mut ch, 1000, 50).contains(&TouchEvent::Leave));
+    }
+
+    #[test]
+    fn test_hand_approaches_and_leaves() {
+        let mut ch = proximity_channel();
+        // Hovering moves the count a third of the way to a touch
+        assert!(feed(&mut ch, 1030, 5).is_empty());
+        assert_eq!(feed(&mut ch, 1030, 1), [TouchEvent::Approach]);
+        assert!(feed(&mut ch, 1030, 50).is_empty());
+        assert!(feed(&mut ch, 1000, 5).is_empty());
+        assert_eq!(feed(&mut ch, 1000, 1), [TouchEvent::Leave]);
+    }
+
+    #[test]
+    fn test_approach_then_touch() {
+        let mut ch = proximity_channel();
+        let events: Vec<_> = (0..60).flat_map(|i| ch.events(1000 + (i * 5).min(100))).collect();
+        assert_eq!(events, [TouchEvent::Approach, TouchEvent::Press]);
+        let events = feed(&mut ch, 1000, 10);
+        assert_eq!(
+            events,
+            [TouchEvent::Release, TouchEvent::Tap, TouchEvent::SingleTap, TouchEvent::Leave]
+        );
+    }
+
+    #[test]
+    fn test_proximity_flicker_is_ignored() {
+        let mut ch = proximity_channel();
+        for _ in 0..10 {
+            assert!(feed(&mut ch, 1030, 5).is_empty());
+            assert!(feed(&mut ch, 1000, 5).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_far_hand_is_not_near() {
+        let mut ch = proximity_channel();
+        assert!(feed(&mut ch, 1010, 50).is_empty());
+    }
+
+    #[test]
+    fn test_hovering_hand_fades_into_baseline() {
+        let mut ch = proximity_channel();
+        let events = feed(&mut ch, 1030, 2000);
+        assert_eq!(events, [TouchEvent::Approach, TouchEvent::Leave]);
+    }
+
+    // --- Sensor faults ---
+
+    const TIMEOUT: u32 = u32::MAX;
+    const FAULT_SAMPLES: usize = 300;
+    /// Raw counts in cycles, for channels with `fault_cycles` set.
+    const HEALTHY: u32 = !2048;
+    const HEALTHY_TOUCH: u32 = HEALTHY + 100;
+
+    fn fault_channel() -> Channel {
+        let config = ChannelConfig::builder().fault_cycles(128, 8192).build().unwrap();
+        let mut ch = Channel::new(config);
+        assert!(feed(&mut ch, HEALTHY, 100).is_empty());
+        ch
+    }
+
+    #[test]
+    fn test_shorted_pad_is_reported_once() {
+        let mut ch = warm_channel();
+        assert!(feed(&mut ch, TIMEOUT, FAULT_SAMPLES - 1).is_empty());
+        assert_eq!(feed(&mut ch, TIMEOUT, 1), [TouchEvent::Fault(SensorFault::Shorted)]);
+        assert!(feed(&mut ch, TIMEOUT, 1000).is_empty());
+        assert_eq!(ch.fault(), Some(SensorFault::Shorted));
+    }
+
+    #[test]
+    fn test_timeouts_are_never_touches() {
+        let mut ch = warm_channel();
+        assert!(feed(&mut ch, TIMEOUT, 50).is_empty());
+        // The pad still works around occasional timeouts
+        assert_eq!(feed(&mut ch, 1100, 50), [TouchEvent::Press]);
+        assert!(feed(&mut ch, TIMEOUT, 50).is_empty());
+        assert_eq!(
+            feed(&mut ch, 1000, 1),
+            [TouchEvent::Release, TouchEvent::Tap, TouchEvent::SingleTap]
+        );
+        assert_eq!(ch.fault(), None);
+    }
+
+    #[test]
+    fn test_fault_cycles_off_by_default() {
+        for raw in [1000, !20_000, !50] {
+            let mut ch = Channel::default();
+            assert!(feed(&mut ch, raw, 2000).is_empty());
+            assert_eq!(ch.fault(), None);
+        }
+    }
+
+    #[test]
+    fn test_open_pad() {
+        let mut ch = fault_channel();
+        let events = feed(&mut ch, !20_000, FAULT_SAMPLES);
+        assert_eq!(events, [TouchEvent::Fault(SensorFault::Open)]);
+    }
+
+    #[test]
+    fn test_saturated_pad() {
+        let mut ch = fault_channel();
+        // Looks like a touch until the fault is confirmed
+        let events = feed(&mut ch, !50, FAULT_SAMPLES);
+        assert_eq!(events, [TouchEvent::Press, TouchEvent::Fault(SensorFault::Saturated)]);
+    }
+
+    #[test]
+    fn test_healthy_pad_with_fault_cycles_works() {
+        let mut ch = fault_channel();
+        assert_eq!(feed(&mut ch, HEALTHY_TOUCH, 50), [TouchEvent::Press]);
+        assert_eq!(
+            feed(&mut ch, HEALTHY, 1),
+            [TouchEvent::Release, TouchEvent::Tap, TouchEvent::SingleTap]
+        );
+    }
+
+    #[test]
+    fn test_fault_drops_touch_in_progress() {
+        let mut ch = warm_channel();
+        assert_eq!(feed(&mut ch, 1100, 400), [TouchEvent::Press, TouchEvent::LongPressStart]);
+        let events = feed(&mut ch, TIMEOUT, FAULT_SAMPLES);
+        assert_eq!(events, [TouchEvent::Fault(SensorFault::Shorted)]);
+        assert!(feed(&mut ch, TIMEOUT, 10).is_empty());
+    }
+
+    #[test]
+    fn test_recovered_pad_warms_up_again() {
+        let mut ch = warm_channel();
+        feed(&mut ch, TIMEOUT, FAULT_SAMPLES);
+        assert!(feed(&mut ch, 1000, FAULT_SAMPLES - 1).is_empty());
+        assert_eq!(feed(&mut ch, 1000, 1), [TouchEvent::Recovered]);
+        assert_eq!(ch.fault(), None);
+        for _ in 0..100 {
+            assert_eq!(ch.state(1000), TouchState::Warmup);
+        }
+        assert_eq!(feed(&mut ch, 1100, 50), [TouchEvent::Press]);
+    }
+
+    #[test]
+    fn test_brief_recovery_keeps_fault() {
+        let mut ch = warm_channel();
+        feed(&mut ch, TIMEOUT, FAULT_SAMPLES);
+        for _ in 0..10 {
+            assert!(feed(&mut ch, 1000, FAULT_SAMPLES / 2).is_empty());
+            assert!(feed(&mut ch, TIMEOUT, 1).is_empty());
+        }
+        assert_eq!(ch.fault(), Some(SensorFault::Shorted));
+    }
+
+    #[test]
+    fn test_fault_can_change() {
+        let mut ch = fault_channel();
+        feed(&mut ch, !20_000, FAULT_SAMPLES);
+        let events = feed(&mut ch, TIMEOUT, FAULT_SAMPLES);
+        assert_eq!(events, [TouchEvent::Fault(SensorFault::Shorted)]);
+    }
+
+    #[test]
+    fn test_stalled_pad() {
+        let mut ch = warm_channel();
+        assert_eq!(ch.stalled().collect::<Vec<_>>(), [TouchEvent::Fault(SensorFault::NoSamples)]);
+        assert_eq!(ch.stalled().count(), 0);
+        assert_eq!(ch.fault(), Some(SensorFault::NoSamples));
+        let events = feed(&mut ch, 1000, FAULT_SAMPLES);
+        assert_eq!(events, [TouchEvent::Recovered]);
+    }
+
+    #[test]
+    fn test_fault_samples() {
+        let config = ChannelConfig::builder().fault_samples(10).build().unwrap();
+        let mut ch = Channel::new(config);
+        feed(&mut ch, 1000, 100);
+        assert_eq!(feed(&mut ch, TIMEOUT, 10), [TouchEvent::Fault(SensorFault::Shorted)]);
+    }
+
+    // --- Config ---
+
+    #[test]
+    fn test_builder_defaults_match_default() {
+        assert_eq!(ChannelConfig::builder().build(), Ok(ChannelConfig::default()));
+    }
+
+    #[test]
+    fn test_builder_rejects_invalid_ranges() {
+        let b = ChannelConfig::builder;
+        assert_eq!(b().warmup(0).build(), Err(ConfigError::ZeroWarmup));
+        assert_eq!(b().touch_threshold(0).build(), Err(ConfigError::TouchThresholdOutOfRange));
+        assert_eq!(b().touch_threshold(LEVEL_ONE).build(), Err(ConfigError::TouchThresholdOutOfRange));
+        assert_eq!(b().tap_min(200).build(), Err(ConfigError::TapMinNotBelowDebounce));
+        assert_eq!(b().debounce(301).build(), Err(ConfigError::DebounceAboveLongThreshold));
+        assert_eq!(b().hold_repeat(0).build(), Err(ConfigError::ZeroHoldRepeat));
+        assert_eq!(b().baseline_shift(0).build(), Err(ConfigError::ShiftOutOfRange));
+        assert_eq!(b().delta_decay_shift(MAX_SHIFT + 1).build(), Err(ConfigError::ShiftOutOfRange));
+        assert_eq!(b().max_hold(300).build(), Err(ConfigError::MaxHoldNotAboveLongThreshold));
+        assert_eq!(b().filter(FilterStage::Median(4)).build(), Err(ConfigError::InvalidFilter));
+        let proximity = |level| b().proximity_threshold(level).build();
+        assert_eq!(proximity(LEVEL_ONE / 2), Err(ConfigError::ProximityThresholdOutOfRange));
+        assert_eq!(proximity(LEVEL_ONE), Err(ConfigError::ProximityThresholdOutOfRange));
+        assert!(proximity(LEVEL_ONE / 2 + 1).is_ok());
+        assert_eq!(b().fault_cycles(100, 100).build(), Err(ConfigError::FaultCyclesOutOfOrder));
+        assert_eq!(b().fault_samples(0).build(), Err(ConfigError::ZeroFaultSamples));
+        let ema = FilterStage::Ema(1);
+        assert_eq!(b().filter(ema).filter(ema).filter(ema).filter(ema).build(), Err(ConfigError::TooManyFilters));
+        assert_eq!(b().sample_rate(0).build(), Err(ConfigError::ZeroSampleRate));
+        assert_eq!(b().tap_min(300).build(), Err(ConfigError::TapMinNotBelowLongThreshold));
+        assert_eq!(b().min_window(MAX_TIMEOUT).build(), Err(ConfigError::MinWindowOutOfRange));
+        assert!(b().min_window(MAX_TIMEOUT - 1).build().is_ok());
+    }
+
+    #[test]
+    fn test_builder_rejects_durations_over_max() {
+        let b = ChannelConfig::builder;
+        let max = MAX_DURATION_S * DEFAULT_SAMPLE_RATE;
+        assert!(b().max_hold(max).build().is_ok());
+        assert_eq!(b().max_hold(max + 1).build(), Err(ConfigError::DurationTooLong));
+        let forever = b().long_threshold(u32::MAX - 1).max_hold(u32::MAX).build();
+        assert_eq!(forever, Err(ConfigError::DurationTooLong));
+        assert_eq!(b().warmup(max + 1).build(), Err(ConfigError::DurationTooLong));
+        assert_eq!(b().fault_samples(max + 1).build(), Err(ConfigError::DurationTooLong));
+        assert_eq!(b().hold_repeat(max + 1).build(), Err(ConfigError::DurationTooLong));
+        assert!(b().multi_tap_window_ms(MAX_DURATION_S * 1000).build().is_ok());
+        let window = b().multi_tap_window_ms(MAX_DURATION_S * 1000 + 1).build();
+        assert_eq!(window, Err(ConfigError::DurationTooLong));
+        // Within the limit at the slowest rate, so within a u32 at the fastest
+        let slow = b().sample_rate(1).max_hold(MAX_DURATION_S).build().unwrap();
+        assert_eq!(slow.at_sample_rate(1200).map(|c| c.max_hold), Ok(MAX_DURATION_S * 1200));
+    }
+
+    #[test]
+    fn test_at_sample_rate_keeps_durations() {
+        let config = ChannelConfig::builder()
+            .multi_tap_window_ms(300)
+            .filter(FilterStage::Median(5))
+            .filter(FilterStage::Notch(6))
+            .filter(FilterStage::Ema(2))
+            .build()
+            .unwrap();
+        assert_eq!(config.at_sample_rate(DEFAULT_SAMPLE_RATE), Ok(config));
+
+        let fast = config.at_sample_rate(1200).unwrap();
+        assert_eq!(fast.sample_rate(), 1200);
+        assert_eq!((fast.warmup, fast.debounce, fast.tap_min), (400, 800, 20));
+        assert_eq!((fast.long_threshold, fast.hold_repeat, fast.multi_tap_window_ms), (1200, 400, 300));
+        assert_eq!((fast.max_hold, fast.fault_samples), (36_000, 1200));
+        assert_eq!((fast.baseline_shift, fast.delta_decay_shift), (9, 11));
+        let filters = [Some(FilterStage::Median(5)), Some(FilterStage::Notch(24)), Some(FilterStage::Ema(4))];
+        assert_eq!(fast.filters, filters);
+        // Counts that are not durations stay put
+        assert_eq!((fast.min_window, fast.touch_threshold), (config.min_window, config.touch_threshold));
+
+        let slow = config.at_sample_rate(75).unwrap();
+        assert_eq!((slow.warmup, slow.debounce, slow.tap_min), (25, 50, 1));
+        assert_eq!((slow.long_threshold, slow.hold_repeat, slow.multi_tap_window_ms), (75, 25, 300));
+        assert_eq!((slow.max_hold, slow.fault_samples), (2250, 75));
+        assert_eq!((slow.baseline_shift, slow.delta_decay_shift), (5, 7));
+        let filters = [Some(FilterStage::Median(5)), Some(FilterStage::Notch(3)), Some(FilterStage::Ema(1))];
+        assert_eq!(slow.filters, filters);
+
+        // Rescaling back and forth lands where it started
+        assert_eq!(slow.at_sample_rate(DEFAULT_SAMPLE_RATE).unwrap().debounce, config.debounce);
+        assert_eq!(config.at_sample_rate(0), Err(ConfigError::ZeroSampleRate));
+    }
+
+    #[test]
+    fn test_long_press_takes_the_same_time_at_any_rate() {
+        for rate in [75, 150, 300, 600, 1200] {
+            let config = ChannelConfig::default().at_sample_rate(rate).unwrap();
+            let (mut ch, touch) = warm(config);
+            // One second held at `rate` samples a second
+            let events = feed(&mut ch, touch, rate as usize);
+            assert_eq!(events, [TouchEvent::Press], "{} samples/s", rate);
+            let events = feed(&mut ch, touch, 2);
+            assert_eq!(events, [TouchEvent::LongPressStart], "{} samples/s", rate);
+        }
+    }
+
+    fn configs() -> Vec<ChannelConfig> {
+        vec![
+            ChannelConfig::default(),
+            // Small pad: low counts, quick response
+            ChannelConfig::builder()
+                .warmup(50)
+                .min_window(10)
+                .debounce(100)
+                .tap_min(3)
+                .long_threshold(150)
+                .hold_repeat(30)
+                .build()
+                .unwrap(),
+            // Large pad in a thick enclosure: noisier, needs more margin
+            ChannelConfig::builder()
+                .warmup(200)
+                .min_window(60)
+                .debounce(300)
+                .touch_threshold(LEVEL_ONE * 2 / 5)
+                .tap_min(10)
+                .long_threshold(500)
+                .hold_repeat(200)
+                .build()
+                .unwrap(),
+            // Auto-ranged to the slowest and fastest sample rates
+            ChannelConfig::default().at_sample_rate(75).unwrap(),
+            ChannelConfig::default().at_sample_rate(1200).unwrap(),
+        ]
+    }
+
+    /// Warm up on a flat baseline; the touch level is well above `min_window`.
+    fn warm(config: ChannelConfig) -> (Channel, u32) {
+        let mut ch = Channel::new(config);
+        for _ in 0..config.warmup {
+            assert_eq!(ch.state(1000), TouchState::Warmup);
+        }
+        (ch, 1000 + 4 * config.min_window + 100)
+    }
+
+    #[test]
+    fn test_configs_warmup_length() {
+        for config in configs() {
+            let (mut ch, _) = warm(config);
+            assert_eq!(ch.state(1000), TouchState::Idle, "{:?}", config);
+        }
+    }
+
+    #[test]
+    fn test_configs_window_below_minimum_is_idle() {
+        for config in configs() {
+            let (mut ch, _) = warm(config);
+            assert!(feed(&mut ch, 1000 + config.min_window, 50).is_empty(), "{:?}", config);
+        }
+    }
+
+    #[test]
+    fn test_configs_tap_minimum() {
+        for config in configs() {
+            let (mut ch, touch) = warm(config);
+            assert!(feed(&mut ch, touch, config.tap_min as usize).is_empty(), "{:?}", config);
+            assert!(feed(&mut ch, 1000, 1).is_empty(), "{:?}", config);
+
+            assert_eq!(feed(&mut ch, touch, config.tap_min as usize + 1), [TouchEvent::Press]);
+            assert_eq!(
+                feed(&mut ch, 1000, 1),
+                [TouchEvent::Release, TouchEvent::Tap, TouchEvent::SingleTap],
+                "{:?}",
+                config
+            );
+        }
+    }
+
+    #[test]
+    fn test_configs_tap_up_to_long_threshold() {
+        for config in configs() {
+            let (mut ch, touch) = warm(config);
+            feed(&mut ch, touch, config.long_threshold as usize);
+            assert_eq!(ch.state(1000), TouchState::Short, "{:?}", config);
+        }
+    }
+
+    #[test]
+    fn test_configs_long_press_and_repeat() {
+        for config in configs() {
+            let (mut ch, touch) = warm(config);
+            let events = feed(&mut ch, touch, config.long_threshold as usize + 2);
+            assert_eq!(events, [TouchEvent::Press, TouchEvent::LongPressStart], "{:?}", config);
+            assert!(feed(&mut ch, touch, config.hold_repeat as usize - 1).is_empty());
+            assert_eq!(feed(&mut ch, touch, 1), [TouchEvent::HoldRepeat], "{:?}", config);
+        }
+    }
 }
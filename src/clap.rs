@@ -0,0 +1,1324 @@
+//! Clap pattern detection from sound-sensor edges.
+//!
+//! A clap is a burst of edges from the sensor's comparator. A burst ends once
+//! no edge has arrived for the debounce time. The gaps between the starts of
+//! successive claps are classed as short or long, and the sequence is matched
+//! against the configured patterns: straight away if no longer pattern could
+//! still match, otherwise once no clap has followed within the window. After
+//! a pattern recognised straight away, edges are ignored for the cooldown.
+//!
+//! The detector also tracks the ambient edge rate. As it rises, a burst
+//! needs more edges to count as a clap, and a burst that goes on for longer
+//! than a clap is dropped as noise. Once the rate stays above the noise
+//! limit (a TV or music, say), edges are ignored altogether until it falls
+//! to half the limit.
+//! A clap counts as a single edge towards the rate, and the rest of the
+//! clap that completed a pattern not at all, so clapping does not make the
+//! room look noisy. Onsets from the microphone (see `EdgeSource`) are one
+//! edge per sound already, so every onset is a clap and the noise limit is
+//! far lower.
+//!
+//! Edges carry their own capture time (see `EdgeClock`), so the timing does
+//! not depend on how promptly the firmware reads them. An edge may reach
+//! the detector up to the configured latency after it happened: ticks hold
+//! back by that much, so no window closes before its edges are in.
+
+use crate::filter::ema;
+
+/// Quiet time that ends a burst of edges.
+pub const DEFAULT_DEBOUNCE_US: u64 = 50_000;
+/// Gaps between clap starts shorter than this are short, longer ones long.
+pub const DEFAULT_SHORT_GAP_US: u64 = 500_000;
+/// Longest gap between the starts of two claps in one pattern.
+pub const DEFAULT_WINDOW_US: u64 = 1_000_000;
+/// Time after a pattern during which edges are ignored.
+pub const DEFAULT_COOLDOWN_US: u64 = 1_000_000;
+/// Most gaps (claps after the first) in a pattern.
+pub const MAX_GAPS: usize = 4;
+/// Most patterns a detector matches against.
+pub const MAX_PATTERNS: usize = 4;
+/// Ambient comparator edges per second above which edges are ignored.
+pub const DEFAULT_NOISE_LIMIT: u32 = 20;
+/// Ambient onsets per second above which onsets are ignored.
+pub const DEFAULT_ONSET_NOISE_LIMIT: u32 = 3;
+/// Longest burst of edges that can be a clap.
+pub const MAX_BURST_US: u64 = 500_000;
+/// Period the edge rate is counted over.
+pub const NOISE_PERIOD_US: u64 = 250_000;
+
+/// Edge rate time constant, as a power of two periods (~2 s).
+const NOISE_SHIFT: u32 = 3;
+const RATE_FRAC_BITS: u32 = 8;
+
+/// Most edges the firmware feeds between ticks: a full PIO RX FIFO, or
+/// the onsets found in two DMA buffers.
+pub const MAX_EDGES_PER_TICK: usize = 8;
+/// Most events one `edge` or `tick` can add: a noise change, a clap ending
+/// and the verdict on it or the cooldown ending, and then the verdict on the
+/// claps before a new one along with its own pattern.
+const MAX_EVENTS_PER_CALL: usize = 5;
+const MAX_PENDING: usize = MAX_EVENTS_PER_CALL * (MAX_EDGES_PER_TICK + 1);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Gap {
+    /// Under the short gap time.
+    Short,
+    /// From the short gap time up to the window.
+    Long,
+}
+
+/// A rhythm of claps, as the gaps between their starts.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ClapPattern {
+    gaps: [Gap; MAX_GAPS],
+    len: usize,
+}
+
+impl ClapPattern {
+    /// Panics if `gaps` is longer than `MAX_GAPS`.
+    pub const fn new(gaps: &[Gap]) -> Self {
+        assert!(gaps.len() <= MAX_GAPS, "too many gaps in a clap pattern");
+        let mut pattern = ClapPattern {
+            gaps: [Gap::Short; MAX_GAPS],
+            len: gaps.len(),
+        };
+        let mut i = 0;
+        while i < gaps.len() {
+            pattern.gaps[i] = gaps[i];
+            i += 1;
+        }
+        pattern
+    }
+
+    pub fn gaps(&self) -> &[Gap] {
+        &self.gaps[..self.len]
+    }
+
+    pub fn claps(&self) -> usize {
+        self.len + 1
+    }
+
+    /// This pattern with one more clap, `gap` after the last.
+    fn then(mut self, gap: Gap) -> Option<Self> {
+        let slot = self.gaps.get_mut(self.len)?;
+        *slot = gap;
+        self.len += 1;
+        Some(self)
+    }
+
+    /// Whether `other` begins with this pattern (or is the same).
+    fn starts(&self, other: &ClapPattern) -> bool {
+        other.gaps().starts_with(self.gaps())
+    }
+}
+
+/// One clap.
+pub const SINGLE: ClapPattern = ClapPattern::new(&[]);
+/// Two quick claps.
+pub const DOUBLE: ClapPattern = ClapPattern::new(&[Gap::Short]);
+/// Three quick claps.
+pub const TRIPLE: ClapPattern = ClapPattern::new(&[Gap::Short, Gap::Short]);
+/// A clap, a pause, then three quick claps.
+pub const LONG_SHORT_SHORT: ClapPattern = ClapPattern::new(&[Gap::Long, Gap::Short, Gap::Short]);
+
+/// What the edges fed to `ClapDetector::edge` are.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EdgeSource {
+    /// Rising edges of the sensor's comparator output (`clap.pio`): a
+    /// burst of them for each sound.
+    Comparator,
+    /// Onsets from `OnsetDetector`: one for each sound.
+    Onsets,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ClapEvent {
+    /// A clap is over and more may follow.
+    Clap,
+    /// The claps matched this pattern. Reported exactly once.
+    Pattern(ClapPattern),
+    /// The claps matched no pattern.
+    Unrecognised,
+    /// The cooldown after a pattern is over.
+    CooldownOver,
+    /// The ambient edge rate reached the noise limit; edges are ignored.
+    TooNoisy,
+    /// The ambient edge rate fell to half the noise limit again.
+    Quiet,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum ClapState {
+    Idle,
+    /// Claps heard so far, the start of the latest one and its latest edge.
+    /// While `in_burst`, the latest is only a clap once `edges` reaches
+    /// `needed`; until then `before` holds the claps before it, if any, and
+    /// the start of the last of them.
+    Claps {
+        heard: ClapPattern,
+        t_start: u64,
+        t_last: u64,
+        in_burst: bool,
+        edges: u32,
+        needed: u32,
+        before: Option<(ClapPattern, u64)>,
+    },
+    /// Ignoring edges until `t_end`. The clap that matched may still be
+    /// going on while `t_last` holds its latest edge.
+    Cooldown {
+        t_end: u64,
+        t_last: Option<u64>,
+    },
+}
+
+pub struct ClapDetector {
+    debounce_us: u64,
+    short_gap_us: u64,
+    window_us: u64,
+    cooldown_us: u64,
+    noise_limit: u32,
+    source: EdgeSource,
+    patterns: [Option<ClapPattern>; MAX_PATTERNS],
+    state: ClapState,
+    /// Edges per second with `RATE_FRAC_BITS` fraction bits, and the edges
+    /// counted so far in the period starting at `period_start`.
+    rate: u32,
+    period_edges: u32,
+    period_start: u64,
+    noisy: bool,
+    /// How far ticks hold back for late edges, and the time acted on so far.
+    latency_us: u64,
+    t_now: u64,
+    /// Events not yet returned by `tick`, oldest first.
+    pending: [Option<ClapEvent>; MAX_PENDING],
+}
+
+impl Default for ClapDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClapDetector {
+    /// A detector for `DOUBLE` only.
+    pub fn new() -> Self {
+        ClapDetector {
+            debounce_us: DEFAULT_DEBOUNCE_US,
+            short_gap_us: DEFAULT_SHORT_GAP_US,
+            window_us: DEFAULT_WINDOW_US,
+            cooldown_us: DEFAULT_COOLDOWN_US,
+            noise_limit: DEFAULT_NOISE_LIMIT,
+            source: EdgeSource::Comparator,
+            patterns: [Some(DOUBLE), None, None, None],
+            state: ClapState::Idle,
+            rate: 0,
+            period_edges: 0,
+            period_start: 0,
+            noisy: false,
+            latency_us: 0,
+            t_now: 0,
+            pending: [None; MAX_PENDING],
+        }
+    }
+
+    /// Set the patterns to recognise. Only the first `MAX_PATTERNS` are used.
+    pub fn set_patterns(&mut self, patterns: &[ClapPattern]) {
+        self.patterns = [None; MAX_PATTERNS];
+        for (slot, &pattern) in self.patterns.iter_mut().zip(patterns) {
+            *slot = Some(pattern);
+        }
+    }
+
+    /// Set the burst debounce, the window (longest gap between claps) and
+    /// the cooldown.
+    pub fn set_timing(&mut self, debounce_us: u64, window_us: u64, cooldown_us: u64) {
+        self.debounce_us = debounce_us;
+        self.window_us = window_us;
+        self.cooldown_us = cooldown_us;
+    }
+
+    /// Set the longest gap that counts as short.
+    pub fn set_short_gap(&mut self, short_gap_us: u64) {
+        self.short_gap_us = short_gap_us;
+    }
+
+    /// Let edges reach `edge` up to `latency_us` after they happened, as
+    /// onsets found in a finished buffer of samples do. `tick` only acts on
+    /// time older than that, so its events come that much later.
+    pub fn set_latency(&mut self, latency_us: u64) {
+        self.latency_us = latency_us;
+    }
+
+    /// Set what the edges are, and the noise limit to that source's default.
+    pub fn set_source(&mut self, source: EdgeSource) {
+        self.source = source;
+        self.noise_limit = match source {
+            EdgeSource::Comparator => DEFAULT_NOISE_LIMIT,
+            EdgeSource::Onsets => DEFAULT_ONSET_NOISE_LIMIT,
+        };
+    }
+
+    /// Set the ambient edges per second at which edges are ignored.
+    pub fn set_noise_limit(&mut self, edges_per_s: u32) {
+        self.noise_limit = edges_per_s.max(1);
+    }
+
+    /// Whether edges are being ignored after a pattern.
+    pub fn is_cooling_down(&self) -> bool {
+        matches!(self.state, ClapState::Cooldown { .. })
+    }
+
+    /// Whether edges are being ignored because of ambient noise.
+    pub fn is_too_noisy(&self) -> bool {
+        self.noisy
+    }
+
+    /// Ambient edges per second, as of the last full period, counting each
+    /// clap as one edge.
+    pub fn edge_rate(&self) -> u32 {
+        self.rate >> RATE_FRAC_BITS
+    }
+
+    /// Edges a burst starting now needs to count as a clap: one, plus twice
+    /// as many as the ambient rate would put within the debounce time. Always
+    /// one for onsets, which stand for a whole sound each.
+    pub fn clap_edges(&self) -> u32 {
+        if self.source == EdgeSource::Onsets {
+            return 1;
+        }
+        let noise = self.rate as u64 * self.debounce_us * 2 / 1_000_000;
+        1 + (noise >> RATE_FRAC_BITS) as u32
+    }
+
+    /// Feed a sound edge seen at `t` (microseconds, monotonic). Any events
+    /// it causes are returned by the following `tick`s. Edges must come in
+    /// order; one later than the latency is taken as happening at the
+    /// oldest time still open.
+    pub fn edge(&mut self, t: u64) {
+        let t = t.max(self.t_now);
+        self.advance(t);
+        if self.noisy {
+            self.period_edges += 1;
+            return;
+        }
+        match self.state {
+            ClapState::Idle => self.clap(SINGLE, t, None),
+            ClapState::Claps {
+                heard,
+                t_start,
+                in_burst,
+                edges,
+                needed,
+                before,
+                ..
+            } => {
+                if in_burst {
+                    if needed == u32::MAX {
+                        // Already counted as noise
+                        self.period_edges += 1;
+                    }
+                    self.burst_edge(heard, t_start, t, edges + 1, needed, before);
+                    return;
+                }
+                let gap = if t.saturating_sub(t_start) < self.short_gap_us {
+                    Gap::Short
+                } else {
+                    Gap::Long
+                };
+                // If nothing can match any more, this clap may start a
+                // pattern of its own
+                let next = heard.then(gap).filter(|heard| self.could_match(heard));
+                self.clap(next.unwrap_or(SINGLE), t, Some((heard, t_start)));
+            }
+            ClapState::Cooldown { t_end, t_last } => {
+                // Edges after the clap that matched are noise
+                let t_last = t_last.filter(|&t_last| t.saturating_sub(t_last) <= self.debounce_us);
+                if t_last.is_none() {
+                    self.period_edges += 1;
+                }
+                self.state = ClapState::Cooldown {
+                    t_end,
+                    t_last: t_last.map(|_| t),
+                };
+            }
+        }
+    }
+
+    /// Apply the time-based transitions up to the latency before `now` and
+    /// return the oldest event not yet returned. Call until it returns
+    /// `None` to see them all; each is returned exactly once, however late
+    /// the tick, as long as no more than `MAX_EDGES_PER_TICK` edges come in
+    /// between ticks.
+    pub fn tick(&mut self, now: u64) -> Option<ClapEvent> {
+        self.advance(now.saturating_sub(self.latency_us));
+        let event = self.pending[0].take();
+        self.pending.rotate_left(1);
+        event
+    }
+
+    fn advance(&mut self, now: u64) {
+        let now = now.max(self.t_now);
+        self.t_now = now;
+        self.measure_noise(now);
+        match self.state {
+            ClapState::Claps {
+                heard,
+                t_start,
+                t_last,
+                mut in_burst,
+                edges,
+                mut needed,
+                before,
+            } => {
+                if in_burst && needed != u32::MAX && now.saturating_sub(t_start) > MAX_BURST_US {
+                    // Too long for a clap: its edges are noise, and it is
+                    // dropped once it ends
+                    self.period_edges += edges;
+                    needed = u32::MAX;
+                }
+                if in_burst && now.saturating_sub(t_last) > self.debounce_us {
+                    if edges < needed {
+                        // Noise rather than a clap: carry on as if it had
+                        // not happened
+                        if needed != u32::MAX {
+                            self.period_edges += edges;
+                        }
+                        self.state = match before {
+                            Some((heard, t_start)) => ClapState::Claps {
+                                heard,
+                                t_start,
+                                t_last,
+                                in_burst: false,
+                                edges,
+                                needed,
+                                before: None,
+                            },
+                            None => ClapState::Idle,
+                        };
+                        return self.advance(now);
+                    }
+                    in_burst = false;
+                    self.period_edges += 1;
+                    self.push(ClapEvent::Clap);
+                }
+                if !in_burst && now.saturating_sub(t_start) >= self.window_us {
+                    self.state = ClapState::Idle;
+                    self.push(if self.matches(&heard) {
+                        ClapEvent::Pattern(heard)
+                    } else {
+                        ClapEvent::Unrecognised
+                    });
+                } else {
+                    self.state = ClapState::Claps {
+                        heard,
+                        t_start,
+                        t_last,
+                        in_burst,
+                        edges,
+                        needed,
+                        before,
+                    };
+                }
+            }
+            ClapState::Cooldown { t_end, .. } => {
+                if now >= t_end {
+                    self.state = ClapState::Idle;
+                    self.push(ClapEvent::CooldownOver);
+                }
+            }
+            ClapState::Idle => (),
+        }
+    }
+
+    /// A burst started at `t` after the claps `before`, making the claps so
+    /// far `heard` if it turns out to be a clap.
+    fn clap(&mut self, heard: ClapPattern, t: u64, before: Option<(ClapPattern, u64)>) {
+        let needed = self.clap_edges();
+        self.burst_edge(heard, t, t, 1, needed, before);
+    }
+
+    /// Edge number `edges` at `t` in the burst started at `t_start`. Once the
+    /// burst has the edges it needs, claps before it that it does not follow
+    /// on from are unrecognised, and it is reported straight away if it
+    /// matches a pattern and no longer one could follow.
+    fn burst_edge(
+        &mut self,
+        heard: ClapPattern,
+        t_start: u64,
+        t: u64,
+        edges: u32,
+        needed: u32,
+        before: Option<(ClapPattern, u64)>,
+    ) {
+        if edges == needed && before.is_some() && heard == SINGLE {
+            self.push(ClapEvent::Unrecognised);
+        }
+        let longer = self.patterns().any(|pattern| pattern != heard && heard.starts(&pattern));
+        if edges == needed && !longer && self.matches(&heard) {
+            self.period_edges += 1;
+            self.push(ClapEvent::Pattern(heard));
+            self.state = ClapState::Cooldown {
+                t_end: t_start + self.cooldown_us,
+                t_last: Some(t),
+            };
+        } else {
+            self.state = ClapState::Claps {
+                heard,
+                t_start,
+                t_last: t,
+                in_burst: true,
+                edges,
+                needed,
+                before,
+            };
+        }
+    }
+
+    /// Fold the edges counted in each period that has ended by `now` into
+    /// the edge rate, and switch to or from ignoring edges.
+    fn measure_noise(&mut self, now: u64) {
+        let periods = now.saturating_sub(self.period_start) / NOISE_PERIOD_US;
+        if periods == 0 {
+            return;
+        }
+        // The rate has decayed to nothing well before this many empty periods
+        for _ in 0..periods.min(64) {
+            let per_s = self.period_edges * (1_000_000 / NOISE_PERIOD_US) as u32;
+            self.rate = ema(self.rate, per_s << RATE_FRAC_BITS, NOISE_SHIFT);
+            self.period_edges = 0;
+        }
+        self.period_start += periods * NOISE_PERIOD_US;
+
+        let rate = self.edge_rate();
+        if !self.noisy && rate >= self.noise_limit {
+            self.noisy = true;
+            self.push(ClapEvent::TooNoisy);
+            if matches!(self.state, ClapState::Claps { .. }) {
+                self.state = ClapState::Idle;
+            }
+        } else if self.noisy && rate < self.noise_limit / 2 {
+            self.noisy = false;
+            self.push(ClapEvent::Quiet);
+        }
+    }
+
+    fn patterns(&self) -> impl Iterator<Item = ClapPattern> + '_ {
+        self.patterns.iter().flatten().copied()
+    }
+
+    fn matches(&self, heard: &ClapPattern) -> bool {
+        self.patterns().any(|pattern| pattern == *heard)
+    }
+
+    fn could_match(&self, heard: &ClapPattern) -> bool {
+        self.patterns().any(|pattern| heard.starts(&pattern))
+    }
+
+    fn push(&mut self, event: ClapEvent) {
+        let full = self.pending[MAX_PENDING - 1].is_some();
+        debug_assert!(!full, "clap events overflowed between ticks");
+        if full {
+            // Keep the newest, which describe the state now
+            self.pending.rotate_left(1);
+            self.pending[MAX_PENDING - 1] = None;
+        }
+        if let Some(slot) = self.pending.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some(event);
+        }
+    }
+}
+
+/// Converts the counts `clap.pio` captures with each edge into timer
+/// microseconds. The counter runs down from `u32::MAX` at one count per
+/// microsecond, wrapping every ~71 minutes.
+#[derive(Clone, Copy, Debug)]
+pub struct EdgeClock {
+    start_us: u64,
+}
+
+impl EdgeClock {
+    /// A counter started at `start_us` on the timer.
+    pub fn new(start_us: u64) -> Self {
+        EdgeClock { start_us }
+    }
+
+    /// State machine clock divisor, as integer and 1/256ths, that runs
+    /// `clap.pio` (two cycles per count) at one count a microsecond from a
+    /// `sys_hz` system clock.
+    pub fn clock_divisor(sys_hz: u32) -> (u16, u8) {
+        const CYCLES_PER_S: u32 = 2_000_000;
+        let frac = (sys_hz % CYCLES_PER_S) as u64 * 256 / CYCLES_PER_S as u64;
+        ((sys_hz / CYCLES_PER_S) as u16, frac as u8)
+    }
+
+    /// Time of an edge captured with count `count`, read at `now`. The edge
+    /// is assumed to be recent, so the age is taken relative to `now` and
+    /// the counter's wraps do not matter. An edge captured after `now` was
+    /// sampled is stamped `now`.
+    pub fn timestamp(&self, count: u32, now: u64) -> u64 {
+        let elapsed = !count;
+        let now_elapsed = now.saturating_sub(self.start_us) as u32;
+        let age = now_elapsed.wrapping_sub(elapsed);
+        if age > i32::MAX as u32 {
+            return now;
+        }
+        now.saturating_sub(age as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: u64 = 1_000;
+
+    /// Every event the ticks at `now` return.
+    fn drain(detector: &mut ClapDetector, now: u64) -> Vec<ClapEvent> {
+        core::iter::from_fn(|| detector.tick(now)).collect()
+    }
+
+    /// Feed edges at each of `times`, ticking at each edge as the firmware
+    /// does, and collect the events.
+    fn claps(detector: &mut ClapDetector, times: &[u64]) -> Vec<ClapEvent> {
+        times
+            .iter()
+            .flat_map(|&t| {
+                detector.edge(t);
+                drain(detector, t)
+            })
+            .collect()
+    }
+
+    /// Tick every millisecond over `from..to` and collect the events.
+    fn run(detector: &mut ClapDetector, from: u64, to: u64) -> Vec<ClapEvent> {
+        (from..to).step_by(MS as usize).flat_map(|now| drain(detector, now)).collect()
+    }
+
+    /// A clap: a few edges a millisecond apart.
+    fn burst(start: u64) -> Vec<u64> {
+        (0..5).map(|i| start + i * MS).collect()
+    }
+
+    /// Claps starting at each of `starts`, ticking every millisecond in
+    /// between, up to `end`.
+    fn clap_at(detector: &mut ClapDetector, starts: &[u64], end: u64) -> Vec<ClapEvent> {
+        let mut events = Vec::new();
+        let mut now = 0;
+        for &start in starts {
+            events.extend(run(detector, now, start));
+            events.extend(claps(detector, &burst(start)));
+            now = start + 5 * MS;
+        }
+        events.extend(run(detector, now, end));
+        events
+    }
+
+    // --- Edge timestamps ---
+
+    /// Count `clap.pio` holds `us` after starting.
+    fn count_at(us: u64) -> u32 {
+        !(us as u32)
+    }
+
+    #[test]
+    fn test_timestamp_is_capture_time() {
+        let clock = EdgeClock::new(5_000);
+        assert_eq!(clock.timestamp(count_at(1_000), 7_000), 6_000);
+        assert_eq!(clock.timestamp(count_at(2_000), 7_000), 7_000);
+    }
+
+    #[test]
+    fn test_timestamp_ignores_read_latency() {
+        let clock = EdgeClock::new(0);
+        let edge = count_at(123_456);
+        for now in [123_456, 130_000, 900_000] {
+            assert_eq!(clock.timestamp(edge, now), 123_456);
+        }
+    }
+
+    #[test]
+    fn test_timestamp_across_counter_wrap() {
+        let clock = EdgeClock::new(1_000);
+        let wrap = 1_000 + (1 << 32);
+        // Captured just before and just after the counter wrapped
+        assert_eq!(clock.timestamp(count_at(u32::MAX as u64 - 9), wrap + 50), wrap - 10);
+        assert_eq!(clock.timestamp(count_at(20), wrap + 50), wrap + 20);
+        let second_wrap = wrap + (1 << 32);
+        assert_eq!(clock.timestamp(count_at(20), second_wrap + 50), second_wrap + 20);
+    }
+
+    #[test]
+    fn test_clock_divisor_follows_system_clock() {
+        assert_eq!(EdgeClock::clock_divisor(125_000_000), (62, 128));
+        assert_eq!(EdgeClock::clock_divisor(133_000_000), (66, 128));
+        assert_eq!(EdgeClock::clock_divisor(48_000_000), (24, 0));
+    }
+
+    #[test]
+    fn test_edge_after_now_is_stamped_now() {
+        let clock = EdgeClock::new(0);
+        assert_eq!(clock.timestamp(count_at(10_005), 10_000), 10_000);
+    }
+
+    // --- Single clap ---
+
+    #[test]
+    fn test_idle_without_edges() {
+        let mut detector = ClapDetector::new();
+        assert!(run(&mut detector, 0, 5_000 * MS).is_empty());
+    }
+
+    #[test]
+    fn test_single_clap_waits_then_expires() {
+        let mut detector = ClapDetector::new();
+        let events = clap_at(&mut detector, &[0], 2_000 * MS);
+        assert_eq!(events, [ClapEvent::Clap, ClapEvent::Unrecognised]);
+    }
+
+    #[test]
+    fn test_burst_ends_after_debounce() {
+        let mut detector = ClapDetector::new();
+        claps(&mut detector, &[0]);
+        assert_eq!(detector.tick(DEFAULT_DEBOUNCE_US), None);
+        assert_eq!(detector.tick(DEFAULT_DEBOUNCE_US + 1), Some(ClapEvent::Clap));
+    }
+
+    #[test]
+    fn test_edges_extend_the_burst() {
+        let mut detector = ClapDetector::new();
+        let edges: Vec<u64> = (0..10).map(|i| i * 40 * MS).collect();
+        assert!(claps(&mut detector, &edges).is_empty());
+        assert_eq!(detector.tick(360 * MS + DEFAULT_DEBOUNCE_US), None);
+        assert_eq!(detector.tick(361 * MS + DEFAULT_DEBOUNCE_US), Some(ClapEvent::Clap));
+    }
+
+    // --- Double clap ---
+
+    #[test]
+    fn test_double_clap_within_window() {
+        let mut detector = ClapDetector::new();
+        claps(&mut detector, &burst(0));
+        assert_eq!(run(&mut detector, 4 * MS, 300 * MS), [ClapEvent::Clap]);
+        assert_eq!(claps(&mut detector, &burst(300 * MS)), [ClapEvent::Pattern(DOUBLE)]);
+        assert!(detector.is_cooling_down());
+    }
+
+    #[test]
+    fn test_long_gap_is_not_a_double_clap() {
+        let mut detector = ClapDetector::new();
+        let events = clap_at(&mut detector, &[0, 700 * MS], 800 * MS);
+        assert_eq!(events, [ClapEvent::Clap, ClapEvent::Unrecognised, ClapEvent::Clap]);
+        // The late clap counts as the first of a new pair
+        assert_eq!(claps(&mut detector, &[900 * MS]), [ClapEvent::Pattern(DOUBLE)]);
+    }
+
+    #[test]
+    fn test_second_burst_before_debounce_is_one_clap() {
+        let mut detector = ClapDetector::new();
+        claps(&mut detector, &burst(0));
+        // Still inside the first burst
+        assert!(claps(&mut detector, &burst(30 * MS)).is_empty());
+        assert_eq!(run(&mut detector, 35 * MS, 200 * MS), [ClapEvent::Clap]);
+    }
+
+    // --- Late second clap ---
+
+    #[test]
+    fn test_late_second_clap_starts_over() {
+        let mut detector = ClapDetector::new();
+        claps(&mut detector, &burst(0));
+        run(&mut detector, 4 * MS, 200 * MS);
+        // At the window's edge, before the expiry tick
+        assert_eq!(claps(&mut detector, &[DEFAULT_WINDOW_US]), [ClapEvent::Unrecognised]);
+        assert!(!detector.is_cooling_down());
+        assert_eq!(
+            run(&mut detector, DEFAULT_WINDOW_US + MS, DEFAULT_WINDOW_US + 100 * MS),
+            [ClapEvent::Clap]
+        );
+    }
+
+    #[test]
+    fn test_clap_after_expiry_is_a_new_first_clap() {
+        let mut detector = ClapDetector::new();
+        claps(&mut detector, &burst(0));
+        run(&mut detector, 4 * MS, 1_500 * MS);
+        claps(&mut detector, &burst(1_500 * MS));
+        assert_eq!(run(&mut detector, 1_505 * MS, 1_800 * MS), [ClapEvent::Clap]);
+        assert_eq!(claps(&mut detector, &burst(1_800 * MS)), [ClapEvent::Pattern(DOUBLE)]);
+    }
+
+    // --- Cooldown ---
+
+    fn double_clap(detector: &mut ClapDetector) -> u64 {
+        claps(detector, &burst(0));
+        run(detector, 4 * MS, 300 * MS);
+        assert_eq!(claps(detector, &[300 * MS]), [ClapEvent::Pattern(DOUBLE)]);
+        300 * MS
+    }
+
+    #[test]
+    fn test_cooldown_ignores_edges() {
+        let mut detector = ClapDetector::new();
+        let t = double_clap(&mut detector);
+        let edges: Vec<u64> = (1..20).map(|i| t + i * 40 * MS).collect();
+        assert!(claps(&mut detector, &edges).is_empty());
+        assert!(detector.is_cooling_down());
+    }
+
+    #[test]
+    fn test_cooldown_ends() {
+        let mut detector = ClapDetector::new();
+        let t = double_clap(&mut detector);
+        assert!(run(&mut detector, t + MS, t + DEFAULT_COOLDOWN_US).is_empty());
+        assert_eq!(detector.tick(t + DEFAULT_COOLDOWN_US), Some(ClapEvent::CooldownOver));
+        assert!(!detector.is_cooling_down());
+        // Ready for the next double clap
+        let t = t + DEFAULT_COOLDOWN_US;
+        claps(&mut detector, &[t]);
+        run(&mut detector, t + MS, t + 300 * MS);
+        assert_eq!(claps(&mut detector, &[t + 300 * MS]), [ClapEvent::Pattern(DOUBLE)]);
+    }
+
+    // --- Loop jitter ---
+
+    /// Xorshift, so the jitter is the same on every run.
+    struct Jitter(u32);
+
+    impl Jitter {
+        /// Next delay in `0..max`.
+        fn next(&mut self, max: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0 as u64 % max
+        }
+    }
+
+    /// Feed edges at `times`, ticking at each edge and then a random delay
+    /// after it, and collect the events.
+    fn late_claps(
+        detector: &mut ClapDetector,
+        times: &[u64],
+        jitter: &mut Jitter,
+        max: u64,
+    ) -> Vec<ClapEvent> {
+        let mut events = Vec::new();
+        for &t in times {
+            detector.edge(t);
+            events.extend(drain(detector, t + jitter.next(max)));
+        }
+        events
+    }
+
+    #[test]
+    fn test_late_tick_still_reports_double_clap() {
+        let mut detector = ClapDetector::new();
+        claps(&mut detector, &burst(0));
+        run(&mut detector, 4 * MS, 300 * MS);
+        detector.edge(300 * MS);
+        assert_eq!(detector.tick(300 * MS + 7), Some(ClapEvent::Pattern(DOUBLE)));
+    }
+
+    #[test]
+    fn test_double_clap_reported_once() {
+        let mut detector = ClapDetector::new();
+        claps(&mut detector, &burst(0));
+        run(&mut detector, 4 * MS, 300 * MS);
+        detector.edge(300 * MS);
+        let events = run(&mut detector, 310 * MS, 300 * MS + DEFAULT_COOLDOWN_US + MS);
+        assert_eq!(events, [ClapEvent::Pattern(DOUBLE), ClapEvent::CooldownOver]);
+    }
+
+    #[test]
+    fn test_tick_after_cooldown_reports_both() {
+        let mut detector = ClapDetector::new();
+        claps(&mut detector, &burst(0));
+        run(&mut detector, 4 * MS, 300 * MS);
+        detector.edge(300 * MS);
+        let late = 300 * MS + 2 * DEFAULT_COOLDOWN_US;
+        assert_eq!(detector.tick(late), Some(ClapEvent::Pattern(DOUBLE)));
+        assert_eq!(detector.tick(late), Some(ClapEvent::CooldownOver));
+        assert_eq!(detector.tick(late), None);
+    }
+
+    #[test]
+    fn test_double_clap_under_loop_jitter() {
+        for seed in 1..=50 {
+            let mut jitter = Jitter(seed);
+            for max_jitter in [1, 100, 5 * MS, 40 * MS] {
+                let mut detector = ClapDetector::new();
+                let mut events = late_claps(&mut detector, &burst(0), &mut jitter, max_jitter);
+                events.extend(run(&mut detector, 50 * MS, 300 * MS));
+                events.extend(late_claps(&mut detector, &burst(300 * MS), &mut jitter, max_jitter));
+                events.extend(run(&mut detector, 350 * MS, 2_000 * MS));
+                assert_eq!(
+                    events,
+                    [ClapEvent::Clap, ClapEvent::Pattern(DOUBLE), ClapEvent::CooldownOver],
+                    "seed {} jitter {}",
+                    seed,
+                    max_jitter
+                );
+            }
+        }
+    }
+
+    /// A `Long` gap only, so the window closing first would lose it.
+    const LONG: ClapPattern = ClapPattern::new(&[Gap::Long]);
+
+    #[test]
+    fn test_late_edges_within_latency() {
+        let mut detector = ClapDetector::new();
+        detector.set_patterns(&[LONG]);
+        detector.set_latency(200 * MS);
+        // Each clap's edges arrive 150 ms late, after ticks past them
+        let mut events = run(&mut detector, 0, 150 * MS);
+        events.extend(claps(&mut detector, &burst(0)));
+        events.extend(run(&mut detector, 150 * MS, 1_050 * MS));
+        for t in burst(900 * MS) {
+            detector.edge(t);
+        }
+        events.extend(run(&mut detector, 1_050 * MS, 1_100 * MS));
+        assert_eq!(events, [ClapEvent::Clap, ClapEvent::Pattern(LONG)]);
+    }
+
+    #[test]
+    fn test_late_edges_without_latency_miss_the_window() {
+        let mut detector = ClapDetector::new();
+        detector.set_patterns(&[LONG]);
+        claps(&mut detector, &burst(0));
+        let mut events = run(&mut detector, 5 * MS, 1_050 * MS);
+        for t in burst(900 * MS) {
+            detector.edge(t);
+        }
+        events.extend(run(&mut detector, 1_050 * MS, 1_200 * MS));
+        assert_eq!(events, [ClapEvent::Clap, ClapEvent::Unrecognised, ClapEvent::Clap]);
+    }
+
+    #[test]
+    fn test_latency_delays_events() {
+        let mut detector = ClapDetector::new();
+        detector.set_latency(20 * MS);
+        claps(&mut detector, &[0]);
+        assert_eq!(detector.tick(DEFAULT_DEBOUNCE_US + 20 * MS), None);
+        assert_eq!(detector.tick(DEFAULT_DEBOUNCE_US + 20 * MS + 1), Some(ClapEvent::Clap));
+    }
+
+    #[test]
+    fn test_edge_later_than_latency_is_clamped() {
+        let mut detector = ClapDetector::new();
+        detector.set_latency(10 * MS);
+        claps(&mut detector, &[0]);
+        run(&mut detector, MS, 410 * MS);
+        // Ticked up to 409 ms, so taken as 399 ms, the oldest time still
+        // open, and the cooldown runs from there
+        detector.edge(10 * MS);
+        assert_eq!(drain(&mut detector, 409 * MS), [ClapEvent::Pattern(DOUBLE)]);
+        assert_eq!(run(&mut detector, 409 * MS, 1_409 * MS), []);
+        assert_eq!(detector.tick(1_409 * MS), Some(ClapEvent::CooldownOver));
+    }
+
+    #[test]
+    fn test_single_clap_under_loop_jitter() {
+        for seed in 1..=50 {
+            let mut jitter = Jitter(seed);
+            let mut detector = ClapDetector::new();
+            let mut events = late_claps(&mut detector, &burst(0), &mut jitter, 40 * MS);
+            events.extend(run(&mut detector, 50 * MS, 2_000 * MS));
+            assert_eq!(events, [ClapEvent::Clap, ClapEvent::Unrecognised], "seed {}", seed);
+        }
+    }
+
+    // --- Patterns ---
+
+    fn all_patterns() -> ClapDetector {
+        let mut detector = ClapDetector::new();
+        detector.set_patterns(&[DOUBLE, TRIPLE, LONG_SHORT_SHORT]);
+        detector
+    }
+
+    #[test]
+    fn test_pattern_gaps() {
+        assert_eq!(SINGLE.claps(), 1);
+        assert_eq!(DOUBLE.gaps(), [Gap::Short]);
+        assert_eq!(TRIPLE.claps(), 3);
+        assert_eq!(LONG_SHORT_SHORT.gaps(), [Gap::Long, Gap::Short, Gap::Short]);
+        assert_eq!(ClapPattern::new(&[Gap::Short]), DOUBLE);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_pattern_too_long() {
+        ClapPattern::new(&[Gap::Short; MAX_GAPS + 1]);
+    }
+
+    #[test]
+    fn test_double_waits_for_a_possible_triple() {
+        let mut detector = all_patterns();
+        let events = clap_at(&mut detector, &[0, 300 * MS], 300 * MS + DEFAULT_WINDOW_US - MS);
+        assert_eq!(events, [ClapEvent::Clap, ClapEvent::Clap]);
+        assert_eq!(
+            drain(&mut detector, 300 * MS + DEFAULT_WINDOW_US),
+            [ClapEvent::Pattern(DOUBLE)]
+        );
+        // Reported once the window is over, so no cooldown is needed
+        assert!(!detector.is_cooling_down());
+    }
+
+    #[test]
+    fn test_triple_is_recognised_at_its_last_clap() {
+        let mut detector = all_patterns();
+        let events = clap_at(&mut detector, &[0, 300 * MS, 600 * MS], 610 * MS);
+        assert_eq!(events, [ClapEvent::Clap, ClapEvent::Clap, ClapEvent::Pattern(TRIPLE)]);
+        assert!(detector.is_cooling_down());
+    }
+
+    #[test]
+    fn test_long_short_short() {
+        let mut detector = all_patterns();
+        let starts = [0, 700 * MS, 1_000 * MS, 1_300 * MS];
+        let events = clap_at(&mut detector, &starts, 1_310 * MS);
+        assert_eq!(
+            events,
+            [ClapEvent::Clap, ClapEvent::Clap, ClapEvent::Clap, ClapEvent::Pattern(LONG_SHORT_SHORT)]
+        );
+    }
+
+    #[test]
+    fn test_incomplete_pattern_is_unrecognised() {
+        let mut detector = all_patterns();
+        let events = clap_at(&mut detector, &[0, 700 * MS], 700 * MS + DEFAULT_WINDOW_US + MS);
+        assert_eq!(events, [ClapEvent::Clap, ClapEvent::Clap, ClapEvent::Unrecognised]);
+    }
+
+    #[test]
+    fn test_single_clap_pattern_is_immediate() {
+        let mut detector = ClapDetector::new();
+        detector.set_patterns(&[SINGLE]);
+        assert_eq!(claps(&mut detector, &[0]), [ClapEvent::Pattern(SINGLE)]);
+    }
+
+    /// A `SINGLE` clap every 1.1 s, just after each cooldown, makes two
+    /// events per edge.
+    fn single_claps(detector: &mut ClapDetector, count: u64) {
+        detector.set_patterns(&[SINGLE]);
+        for i in 0..count {
+            detector.edge(i * 1_100 * MS);
+        }
+    }
+
+    #[test]
+    fn test_many_events_before_a_tick() {
+        let mut detector = ClapDetector::new();
+        single_claps(&mut detector, MAX_EDGES_PER_TICK as u64);
+        let events = drain(&mut detector, 9_000 * MS);
+        let mut expected = vec![ClapEvent::Pattern(SINGLE)];
+        for _ in 1..MAX_EDGES_PER_TICK {
+            expected.extend([ClapEvent::CooldownOver, ClapEvent::Pattern(SINGLE)]);
+        }
+        expected.push(ClapEvent::CooldownOver);
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    #[should_panic(expected = "overflowed")]
+    fn test_overflow_is_caught() {
+        let mut detector = ClapDetector::new();
+        single_claps(&mut detector, MAX_PENDING as u64);
+    }
+
+    #[test]
+    fn test_single_and_double() {
+        let mut detector = ClapDetector::new();
+        detector.set_patterns(&[SINGLE, DOUBLE]);
+        let events = clap_at(&mut detector, &[0], 2_000 * MS);
+        assert_eq!(events, [ClapEvent::Clap, ClapEvent::Pattern(SINGLE)]);
+        let events = claps(&mut detector, &[3_000 * MS, 3_200 * MS]);
+        assert_eq!(events, [ClapEvent::Clap, ClapEvent::Pattern(DOUBLE)]);
+    }
+
+    #[test]
+    fn test_set_short_gap() {
+        let mut detector = ClapDetector::new();
+        detector.set_short_gap(200 * MS);
+        let events = clap_at(&mut detector, &[0, 300 * MS], 310 * MS);
+        assert_eq!(events, [ClapEvent::Clap, ClapEvent::Unrecognised]);
+    }
+
+    #[test]
+    fn test_set_timing() {
+        let mut detector = ClapDetector::new();
+        detector.set_timing(10 * MS, 200 * MS, 100 * MS);
+        claps(&mut detector, &[0]);
+        assert_eq!(detector.tick(11 * MS), Some(ClapEvent::Clap));
+        assert_eq!(claps(&mut detector, &[250 * MS]), [ClapEvent::Unrecognised]);
+        assert_eq!(detector.tick(261 * MS), Some(ClapEvent::Clap));
+        assert_eq!(claps(&mut detector, &[300 * MS]), [ClapEvent::Pattern(DOUBLE)]);
+        assert_eq!(detector.tick(400 * MS), Some(ClapEvent::CooldownOver));
+    }
+
+    // --- Ambient noise ---
+
+    /// Random edges averaging `per_s` a second over `from..to`.
+    fn noise(jitter: &mut Jitter, per_s: u64, from: u64, to: u64) -> Vec<u64> {
+        let mut edges = Vec::new();
+        let mut t = from as f64;
+        loop {
+            // Exponential gaps, as between independent random events
+            let u = (jitter.next(1_000_000) + 1) as f64 / 1_000_001.0;
+            t -= u.ln() * 1e6 / per_s as f64;
+            if t >= to as f64 {
+                return edges;
+            }
+            edges.push(t as u64);
+        }
+    }
+
+    /// Feed `edges` in order, ticking every millisecond in between, up to
+    /// `end`, and collect the events.
+    fn listen(detector: &mut ClapDetector, edges: &[u64], end: u64) -> Vec<ClapEvent> {
+        let mut edges = edges.to_vec();
+        edges.sort();
+        let mut events = Vec::new();
+        let mut now = 0;
+        for t in edges {
+            events.extend(run(detector, now, t));
+            events.extend(claps(detector, &[t]));
+            now = t;
+        }
+        events.extend(run(detector, now, end));
+        events
+    }
+
+    /// Two claps 300 ms apart from `start`.
+    fn double_clap_at(start: u64) -> Vec<u64> {
+        [burst(start), burst(start + 300 * MS)].concat()
+    }
+
+    #[test]
+    fn test_rate_follows_density() {
+        for (seed, per_s) in [(1, 2), (2, 5), (3, 10), (4, 15)] {
+            let mut jitter = Jitter(seed);
+            let mut detector = ClapDetector::new();
+            listen(&mut detector, &noise(&mut jitter, per_s, 0, 10_000 * MS), 10_000 * MS);
+            let rate = detector.edge_rate() as u64;
+            assert!(rate.abs_diff(per_s) <= per_s / 2 + 1, "{} edges/s estimated as {}", per_s, rate);
+            assert!(!detector.is_too_noisy());
+        }
+    }
+
+    #[test]
+    fn test_rate_decays_in_silence() {
+        let mut detector = ClapDetector::new();
+        let ticking: Vec<u64> = (0..60).map(|i| i * 80 * MS).collect();
+        listen(&mut detector, &ticking, 5_000 * MS);
+        assert!(detector.clap_edges() > 1);
+        run(&mut detector, 5_000 * MS, 15_000 * MS);
+        assert_eq!(detector.edge_rate(), 0);
+        assert_eq!(detector.clap_edges(), 1);
+    }
+
+    #[test]
+    fn test_double_clap_in_sparse_noise() {
+        for seed in 1..=20 {
+            let mut jitter = Jitter(seed);
+            let mut detector = ClapDetector::new();
+            let mut edges = noise(&mut jitter, 1, 0, 5_000 * MS);
+            // Keep the claps clear of noise edges
+            edges.retain(|&t| !(2_900 * MS..3_400 * MS).contains(&t));
+            edges.extend(double_clap_at(3_000 * MS));
+            let events = listen(&mut detector, &edges, 5_000 * MS);
+            assert!(events.contains(&ClapEvent::Pattern(DOUBLE)), "seed {}", seed);
+            assert!(!events.contains(&ClapEvent::TooNoisy), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn test_moderate_noise_raises_clap_edges() {
+        let mut detector = ClapDetector::new();
+        // Isolated edges (12.5 a second) that would each be a clap in a
+        // quiet room
+        let ticking: Vec<u64> = (0..125).map(|i| i * 80 * MS).collect();
+        let warmup = listen(&mut detector, &ticking[..50], 4_000 * MS);
+        assert!(warmup.contains(&ClapEvent::Pattern(DOUBLE)));
+        assert_eq!(detector.clap_edges(), 2);
+        let events = listen(&mut detector, &ticking[50..], 10_000 * MS);
+        assert!(events.iter().all(|&event| event != ClapEvent::Pattern(DOUBLE)), "{:?}", events);
+        assert!(!detector.is_too_noisy());
+
+        // Real claps still get through
+        let mut edges: Vec<u64> = (125..150).map(|i| i * 80 * MS).collect();
+        edges.extend(double_clap_at(11_000 * MS));
+        let events = listen(&mut detector, &edges, 12_000 * MS);
+        assert_eq!(events.iter().filter(|&&event| event == ClapEvent::Pattern(DOUBLE)).count(), 1);
+    }
+
+    #[test]
+    fn test_dense_noise_is_too_noisy() {
+        for (seed, per_s) in [(1, 40), (2, 100), (3, 400)] {
+            let mut jitter = Jitter(seed);
+            let mut detector = ClapDetector::new();
+            let mut edges = noise(&mut jitter, per_s, 0, 10_000 * MS);
+            edges.extend(double_clap_at(6_000 * MS));
+            let events = listen(&mut detector, &edges, 10_000 * MS);
+            assert!(detector.is_too_noisy(), "{} edges/s", per_s);
+            let noisy = events.iter().position(|&event| event == ClapEvent::TooNoisy).unwrap();
+            assert!(
+                events[noisy + 1..].iter().all(|&event| event == ClapEvent::CooldownOver),
+                "{} edges/s: {:?}",
+                per_s,
+                events
+            );
+        }
+    }
+
+    fn onsets() -> ClapDetector {
+        let mut detector = ClapDetector::new();
+        detector.set_source(EdgeSource::Onsets);
+        detector
+    }
+
+    #[test]
+    fn test_onset_claps_need_one_edge_in_noise() {
+        for seed in 1..=20 {
+            let mut jitter = Jitter(seed);
+            let mut detector = onsets();
+            let mut edges = noise(&mut jitter, 1, 0, 10_000 * MS);
+            // Clear of noise for a window before the claps, too
+            edges.retain(|&t| !(5_000 * MS..6_400 * MS).contains(&t));
+            edges.extend([6_000 * MS, 6_300 * MS]);
+            let events = listen(&mut detector, &edges, 10_000 * MS);
+            assert!(events.contains(&ClapEvent::Pattern(DOUBLE)), "seed {}: {:?}", seed, events);
+            assert!(!events.contains(&ClapEvent::TooNoisy), "seed {}", seed);
+            assert_eq!(detector.clap_edges(), 1);
+        }
+    }
+
+    #[test]
+    fn test_onset_noise_limit() {
+        let mut jitter = Jitter(6);
+        let edges = noise(&mut jitter, 5, 0, 10_000 * MS);
+        let mut detector = onsets();
+        let events = listen(&mut detector, &edges, 10_000 * MS);
+        assert!(detector.is_too_noisy(), "{} onsets/s", detector.edge_rate());
+        assert_eq!(events.iter().filter(|&&event| event == ClapEvent::TooNoisy).count(), 1);
+        let events = run(&mut detector, 10_000 * MS, 15_000 * MS);
+        assert!(events.contains(&ClapEvent::Quiet));
+        // The same rate of comparator edges is quiet
+        let mut detector = ClapDetector::new();
+        listen(&mut detector, &edges, 10_000 * MS);
+        assert!(!detector.is_too_noisy());
+    }
+
+    #[test]
+    fn test_repeated_onset_double_claps_are_not_noise() {
+        let mut detector = onsets();
+        let mut edges = Vec::new();
+        for i in 0..10 {
+            let start = i * 1_400 * MS;
+            edges.extend([start, start + 300 * MS]);
+        }
+        let events = listen(&mut detector, &edges, 15_000 * MS);
+        assert_eq!(events.iter().filter(|&&event| event == ClapEvent::Pattern(DOUBLE)).count(), 10);
+        assert!(!events.contains(&ClapEvent::TooNoisy));
+    }
+
+    #[test]
+    fn test_quiet_after_noise() {
+        let mut jitter = Jitter(5);
+        let mut detector = ClapDetector::new();
+        let events = listen(&mut detector, &noise(&mut jitter, 100, 0, 3_000 * MS), 3_000 * MS);
+        assert_eq!(events.iter().filter(|&&event| event == ClapEvent::TooNoisy).count(), 1);
+        let events = run(&mut detector, 3_000 * MS, 8_000 * MS);
+        assert_eq!(events.iter().filter(|&&event| event == ClapEvent::Quiet).count(), 1);
+        assert!(!detector.is_too_noisy());
+        let events = clap_at(&mut detector, &[8_000 * MS, 8_300 * MS], 8_400 * MS);
+        assert!(events.contains(&ClapEvent::Pattern(DOUBLE)), "{:?}", events);
+    }
+
+    #[test]
+    fn test_endless_burst_is_not_a_clap() {
+        let mut detector = ClapDetector::new();
+        let hum: Vec<u64> = (0..30).map(|i| i * 20 * MS).collect();
+        assert!(listen(&mut detector, &hum, 1_000 * MS).is_empty());
+        assert!(!detector.is_too_noisy());
+        let events = clap_at(&mut detector, &[2_000 * MS, 2_300 * MS], 2_400 * MS);
+        assert_eq!(events, [ClapEvent::Clap, ClapEvent::Pattern(DOUBLE)]);
+    }
+
+    #[test]
+    fn test_noise_between_claps_is_skipped() {
+        let mut detector = all_patterns();
+        // A burst that goes on too long to be a clap, between the first and
+        // second claps of a pattern
+        let mut edges = burst(0);
+        edges.extend((0..30).map(|i| 100 * MS + i * 20 * MS));
+        for start in [800 * MS, 1_100 * MS, 1_400 * MS] {
+            edges.extend(burst(start));
+        }
+        let events = listen(&mut detector, &edges, 1_410 * MS);
+        assert_eq!(
+            events,
+            [ClapEvent::Clap, ClapEvent::Clap, ClapEvent::Clap, ClapEvent::Pattern(LONG_SHORT_SHORT)]
+        );
+    }
+
+    /// A clap as a real sensor hears it: `edges` edges a millisecond apart.
+    fn loud_burst(start: u64, edges: u64) -> Vec<u64> {
+        (0..edges).map(|i| start + i * MS).collect()
+    }
+
+    #[test]
+    fn test_loud_claps_are_not_noise() {
+        for edges in [20, 35, 50] {
+            let mut detector = all_patterns();
+            let starts = [0, 800 * MS, 1_100 * MS, 1_400 * MS];
+            let claps: Vec<u64> = starts.iter().flat_map(|&start| loud_burst(start, edges)).collect();
+            let events = listen(&mut detector, &claps, 3_000 * MS);
+            assert_eq!(
+                events,
+                [
+                    ClapEvent::Clap,
+                    ClapEvent::Clap,
+                    ClapEvent::Clap,
+                    ClapEvent::Pattern(LONG_SHORT_SHORT),
+                    ClapEvent::CooldownOver
+                ],
+                "{} edges a clap",
+                edges
+            );
+            assert_eq!(detector.clap_edges(), 1, "{} edges a clap", edges);
+        }
+    }
+
+    #[test]
+    fn test_repeated_loud_double_claps() {
+        let mut detector = ClapDetector::new();
+        let claps: Vec<u64> = (0..10)
+            .flat_map(|i| [loud_burst(i * 2_000 * MS, 40), loud_burst(i * 2_000 * MS + 300 * MS, 40)])
+            .flatten()
+            .collect();
+        let events = listen(&mut detector, &claps, 20_000 * MS);
+        let doubles = events.iter().filter(|&&event| event == ClapEvent::Pattern(DOUBLE)).count();
+        assert_eq!(doubles, 10, "{:?}", events);
+        assert!(!events.contains(&ClapEvent::TooNoisy));
+    }
+
+    #[test]
+    fn test_noise_during_cooldown_counts() {
+        let mut jitter = Jitter(11);
+        let mut detector = ClapDetector::new();
+        let mut edges = double_clap_at(0);
+        edges.extend(noise(&mut jitter, 100, 400 * MS, 3_000 * MS));
+        let events = listen(&mut detector, &edges, 3_000 * MS);
+        assert!(events.contains(&ClapEvent::TooNoisy), "{:?}", events);
+    }
+
+    #[test]
+    fn test_set_noise_limit() {
+        let mut jitter = Jitter(9);
+        let mut detector = ClapDetector::new();
+        detector.set_noise_limit(200);
+        listen(&mut detector, &noise(&mut jitter, 100, 0, 5_000 * MS), 5_000 * MS);
+        assert!(!detector.is_too_noisy());
+    }
+}
@@ -0,0 +1,100 @@
+use smart_leds::RGB8;
+
+pub const MIN_KELVIN: u16 = 1000;
+pub const MAX_KELVIN: u16 = 10_000;
+const KELVIN_STEP: u16 = 500;
+
+/// Approximate blackbody colours from `MIN_KELVIN` to `MAX_KELVIN` in
+/// `KELVIN_STEP` increments, normalised so the brightest channel is 255.
+const BLACKBODY: [(u8, u8, u8); 19] = [
+    (255, 56, 0),    // 1000K
+    (255, 109, 0),   // 1500K
+    (255, 137, 14),  // 2000K
+    (255, 161, 72),  // 2500K
+    (255, 180, 107), // 3000K
+    (255, 196, 137), // 3500K
+    (255, 209, 163), // 4000K
+    (255, 219, 186), // 4500K
+    (255, 228, 206), // 5000K
+    (255, 236, 224), // 5500K
+    (255, 243, 239), // 6000K
+    (255, 249, 253), // 6500K
+    (245, 243, 255), // 7000K
+    (235, 238, 255), // 7500K
+    (227, 233, 255), // 8000K
+    (220, 229, 255), // 8500K
+    (214, 225, 255), // 9000K
+    (208, 222, 255), // 9500K
+    (204, 219, 255), // 10000K
+];
+
+/// Colour the light is dimmed towards. The light level only scales this, so
+/// the hue is kept while fading.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Colour {
+    Rgb(RGB8),
+    /// Colour temperature in kelvin, clamped to `MIN_KELVIN..=MAX_KELVIN`.
+    Temperature(u16),
+}
+
+impl Colour {
+    pub fn to_rgb(self) -> RGB8 {
+        match self {
+            Colour::Rgb(rgb) => rgb,
+            Colour::Temperature(kelvin) => kelvin_to_rgb(kelvin),
+        }
+    }
+}
+
+/// Linearly interpolate the blackbody table at `kelvin`.
+pub fn kelvin_to_rgb(kelvin: u16) -> RGB8 {
+    let offset = kelvin.clamp(MIN_KELVIN, MAX_KELVIN) - MIN_KELVIN;
+    let index = (offset / KELVIN_STEP) as usize;
+    let frac = (offset % KELVIN_STEP) as i32;
+    let lo = BLACKBODY[index];
+    let hi = BLACKBODY[(index + 1).min(BLACKBODY.len() - 1)];
+    let lerp = |a: u8, b: u8| (a as i32 + (b as i32 - a as i32) * frac / KELVIN_STEP as i32) as u8;
+    RGB8::new(lerp(lo.0, hi.0), lerp(lo.1, hi.1), lerp(lo.2, hi.2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kelvin_table_points() {
+        assert_eq!(kelvin_to_rgb(1000), RGB8::new(255, 56, 0));
+        assert_eq!(kelvin_to_rgb(2000), RGB8::new(255, 137, 14));
+        assert_eq!(kelvin_to_rgb(6500), RGB8::new(255, 249, 253));
+        assert_eq!(kelvin_to_rgb(10_000), RGB8::new(204, 219, 255));
+    }
+
+    #[test]
+    fn test_kelvin_interpolates_between_points() {
+        // Halfway between 2000K and 2500K
+        assert_eq!(kelvin_to_rgb(2250), RGB8::new(255, 149, 43));
+    }
+
+    #[test]
+    fn test_kelvin_is_clamped() {
+        assert_eq!(kelvin_to_rgb(0), kelvin_to_rgb(MIN_KELVIN));
+        assert_eq!(kelvin_to_rgb(u16::MAX), kelvin_to_rgb(MAX_KELVIN));
+    }
+
+    #[test]
+    fn test_warmer_has_less_blue() {
+        let mut last = 0;
+        for kelvin in (MIN_KELVIN..=6000).step_by(100) {
+            let rgb = kelvin_to_rgb(kelvin);
+            assert!(rgb.b >= last, "kelvin: {}", kelvin);
+            assert!(rgb.r >= rgb.g && rgb.g >= rgb.b, "kelvin: {}", kelvin);
+            last = rgb.b;
+        }
+    }
+
+    #[test]
+    fn test_colour_to_rgb() {
+        assert_eq!(Colour::Rgb(RGB8::new(1, 2, 3)).to_rgb(), RGB8::new(1, 2, 3));
+        assert_eq!(Colour::Temperature(1500).to_rgb(), RGB8::new(255, 109, 0));
+    }
+}
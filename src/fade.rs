@@ -0,0 +1,157 @@
+/// Fixed-point 1.0 for fade progress (Q16).
+pub const PROGRESS_ONE: u32 = 1 << 16;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Easing {
+    Linear,
+    /// Smoothstep: slow start and finish, fastest in the middle.
+    EaseInOut,
+    /// `(2^(10t) - 1) / 1023`: barely moves at first, most of the change at
+    /// the end.
+    Exponential,
+}
+
+impl Easing {
+    /// Map linear progress in `0..=PROGRESS_ONE` to eased progress in the
+    /// same range. Every curve is monotonic and hits both end points exactly.
+    pub fn apply(self, t: u32) -> u32 {
+        let t = t.min(PROGRESS_ONE) as u64;
+        let one = PROGRESS_ONE as u64;
+        let eased = match self {
+            Easing::Linear => t,
+            Easing::EaseInOut => t * t / one * (3 * one - 2 * t) / one,
+            Easing::Exponential => {
+                // Piecewise-linear 2^x between integer powers of two
+                let x = 10 * t;
+                let pow2 = (one + (x % one)) << (x / one);
+                (pow2 - one) / 1023
+            }
+        };
+        eased as u32
+    }
+}
+
+/// A brightness ramp between two levels over a fixed duration.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Fade {
+    from: u8,
+    to: u8,
+    start_us: u64,
+    duration_us: u64,
+    easing: Easing,
+}
+
+impl Fade {
+    pub fn new(from: u8, to: u8, start_us: u64, duration_ms: u32, easing: Easing) -> Self {
+        Fade {
+            from,
+            to,
+            start_us,
+            duration_us: duration_ms as u64 * 1000,
+            easing,
+        }
+    }
+
+    pub fn target(&self) -> u8 {
+        self.to
+    }
+
+    pub fn is_done(&self, now_us: u64) -> bool {
+        now_us.saturating_sub(self.start_us) >= self.duration_us
+    }
+
+    /// Level at `now_us`. Times before the start hold `from`, times past the
+    /// end hold `to`.
+    pub fn level(&self, now_us: u64) -> u8 {
+        let elapsed = now_us.saturating_sub(self.start_us);
+        if elapsed >= self.duration_us {
+            return self.to;
+        }
+        let t = (elapsed * PROGRESS_ONE as u64 / self.duration_us) as u32;
+        let eased = self.easing.apply(t) as i32;
+        let span = self.to as i32 - self.from as i32;
+        (self.from as i32 + span * eased / PROGRESS_ONE as i32) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CURVES: [Easing; 3] = [Easing::Linear, Easing::EaseInOut, Easing::Exponential];
+
+    // --- Easing ---
+
+    #[test]
+    fn test_easing_end_points() {
+        for easing in CURVES {
+            assert_eq!(easing.apply(0), 0, "{:?}", easing);
+            assert_eq!(easing.apply(PROGRESS_ONE), PROGRESS_ONE, "{:?}", easing);
+            assert_eq!(easing.apply(u32::MAX), PROGRESS_ONE, "{:?}", easing);
+        }
+    }
+
+    #[test]
+    fn test_easing_is_monotonic() {
+        for easing in CURVES {
+            let mut last = 0;
+            for t in (0..=PROGRESS_ONE).step_by(64) {
+                let eased = easing.apply(t);
+                assert!(eased >= last, "{:?} at {}", easing, t);
+                last = eased;
+            }
+        }
+    }
+
+    #[test]
+    fn test_easing_midpoints() {
+        let half = PROGRESS_ONE / 2;
+        assert_eq!(Easing::Linear.apply(half), half);
+        assert_eq!(Easing::EaseInOut.apply(half), half);
+        // 2^5 - 1 = 31 out of 1023
+        assert_eq!(Easing::Exponential.apply(half), 31 * PROGRESS_ONE / 1023);
+    }
+
+    #[test]
+    fn test_ease_in_out_is_slow_at_the_ends() {
+        let tenth = PROGRESS_ONE / 10;
+        assert!(Easing::EaseInOut.apply(tenth) < tenth);
+        assert!(Easing::EaseInOut.apply(PROGRESS_ONE - tenth) > PROGRESS_ONE - tenth);
+    }
+
+    // --- Fade ---
+
+    #[test]
+    fn test_fade_linear_rising() {
+        let fade = Fade::new(0, 200, 1_000, 100, Easing::Linear);
+        assert_eq!(fade.level(0), 0);
+        assert_eq!(fade.level(1_000), 0);
+        assert_eq!(fade.level(26_000), 50);
+        assert_eq!(fade.level(51_000), 100);
+        assert_eq!(fade.level(101_000), 200);
+        assert_eq!(fade.level(u64::MAX), 200);
+    }
+
+    #[test]
+    fn test_fade_linear_falling() {
+        let fade = Fade::new(200, 0, 0, 100, Easing::Linear);
+        assert_eq!(fade.level(25_000), 150);
+        assert_eq!(fade.level(50_000), 100);
+        assert_eq!(fade.level(100_000), 0);
+    }
+
+    #[test]
+    fn test_fade_done_after_duration() {
+        let fade = Fade::new(0, 255, 500, 10, Easing::EaseInOut);
+        assert!(!fade.is_done(10_499));
+        assert!(fade.is_done(10_500));
+        assert_eq!(fade.target(), 255);
+    }
+
+    #[test]
+    fn test_fade_zero_duration_jumps() {
+        let fade = Fade::new(10, 20, 0, 0, Easing::Linear);
+        assert!(fade.is_done(0));
+        assert_eq!(fade.level(0), 20);
+    }
+}
@@ -0,0 +1,492 @@
+//! Filters applied to raw touch counts before classification.
+//!
+//! Each stage is a small fixed-size integer filter, so a chain of them costs
+//! a few dozen cycles per sample and needs no allocation or FPU.
+
+/// Longest median window.
+pub const MAX_MEDIAN: usize = 9;
+/// Longest notch (moving average) period: 50 Hz at the fastest auto-ranged
+/// sample rate is 24.
+pub const MAX_NOTCH_PERIOD: usize = 32;
+/// Longest EMA time constant, as a power of two samples.
+pub const MAX_EMA_SHIFT: u32 = 8;
+/// Most stages a `FilterChain` can hold.
+pub const MAX_STAGES: usize = 3;
+
+const EMA_FRAC_BITS: u32 = 8;
+
+/// A filter over a stream of raw counts.
+pub trait Filter {
+    /// Feed one sample and return the filtered value.
+    fn apply(&mut self, raw: u32) -> u32;
+
+    /// Whether enough samples have been fed for the output to be a full
+    /// filter of the input, rather than of the few samples seen so far.
+    fn is_settled(&self) -> bool {
+        true
+    }
+}
+
+/// One configurable filter stage.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FilterStage {
+    /// Median of the last `n` samples (odd, `3..=MAX_MEDIAN`). Removes short
+    /// spikes, such as EMI from the LED's SPI bursts, without smearing edges.
+    Median(usize),
+    /// Exponential moving average with a time constant of `2^shift` samples
+    /// (`1..=MAX_EMA_SHIFT`).
+    Ema(u32),
+    /// Mean of the last `period` samples (`2..=MAX_NOTCH_PERIOD`): a comb
+    /// filter that nulls hum with that period and its harmonics. At ~300
+    /// samples/s, 50 Hz mains is a period of 6 and 60 Hz a period of 5.
+    Notch(usize),
+}
+
+/// Hum may drift this fraction of a cycle from a whole number of cycles over
+/// a rescaled notch period.
+const NOTCH_TOLERANCE: u64 = 50;
+
+impl FilterStage {
+    pub fn is_valid(self) -> bool {
+        match self {
+            FilterStage::Median(n) => n % 2 == 1 && (3..=MAX_MEDIAN).contains(&n),
+            FilterStage::Ema(shift) => (1..=MAX_EMA_SHIFT).contains(&shift),
+            FilterStage::Notch(period) => (2..=MAX_NOTCH_PERIOD).contains(&period),
+        }
+    }
+
+    /// The same filter for samples taken `to` rather than `from` times a
+    /// second. An EMA keeps its time constant. A notch keeps nulling the
+    /// same hum frequency, which above half the sample rate shows up
+    /// aliased, so it takes the shortest period that spans a whole number of
+    /// hum cycles. A median window is in samples, as the spikes it removes
+    /// are single samples at any rate.
+    pub fn at_sample_rate(self, from: u32, to: u32) -> Self {
+        match self {
+            FilterStage::Median(n) => FilterStage::Median(n),
+            FilterStage::Ema(shift) => FilterStage::Ema(rescale_shift(shift, from, to).clamp(1, MAX_EMA_SHIFT)),
+            FilterStage::Notch(period) => FilterStage::Notch(notch_period(period, from, to)),
+        }
+    }
+}
+
+/// `count` samples at `from` a second, as samples at `to` a second: the
+/// same length of time, rounded to the nearest sample but at least one.
+pub(crate) fn rescale(count: u32, from: u32, to: u32) -> u32 {
+    if count == 0 {
+        return 0;
+    }
+    let scaled = (count as u64 * to as u64 + from as u64 / 2) / from as u64;
+    scaled.clamp(1, u32::MAX as u64) as u32
+}
+
+/// A time constant of `2^shift` samples at `from` a second, as the nearest
+/// power of two samples at `to` a second.
+pub(crate) fn rescale_shift(shift: u32, from: u32, to: u32) -> u32 {
+    let (mut shift, mut rate, to) = (shift, from as u64, to as u64);
+    // Step while the ratio left is at least sqrt(2)
+    while to * to >= 2 * rate * rate {
+        rate *= 2;
+        shift += 1;
+    }
+    while rate * rate >= 2 * to * to && shift > 0 {
+        rate /= 2;
+        shift -= 1;
+    }
+    shift
+}
+
+/// Shortest notch period at `to` samples a second that nulls the hum a
+/// `period` notch nulls at `from`, falling back to the same length of time.
+fn notch_period(period: usize, from: u32, to: u32) -> usize {
+    // Hum cycles over n samples: n * (from / period) / to
+    let cycles_den = period as u64 * to as u64;
+    (2..=MAX_NOTCH_PERIOD)
+        .find(|&n| {
+            let cycles_num = n as u64 * from as u64;
+            let whole = ((cycles_num + cycles_den / 2) / cycles_den).max(1);
+            cycles_num.abs_diff(whole * cycles_den) * NOTCH_TOLERANCE <= cycles_den
+        })
+        .unwrap_or_else(|| (rescale(period as u32, from, to) as usize).clamp(2, MAX_NOTCH_PERIOD))
+}
+
+/// Fixed-length history of the most recent samples.
+#[derive(Clone, Copy, Debug)]
+struct Window<const N: usize> {
+    samples: [u32; N],
+    len: usize,
+    next: usize,
+    filled: usize,
+}
+
+impl<const N: usize> Window<N> {
+    fn new(len: usize) -> Self {
+        Window {
+            samples: [0; N],
+            len: len.clamp(1, N),
+            next: 0,
+            filled: 0,
+        }
+    }
+
+    /// Store `raw`, returning the sample it replaced once the window is full.
+    fn push(&mut self, raw: u32) -> Option<u32> {
+        let old = (self.filled == self.len).then_some(self.samples[self.next]);
+        self.samples[self.next] = raw;
+        self.next = (self.next + 1) % self.len;
+        self.filled = (self.filled + 1).min(self.len);
+        old
+    }
+
+    fn filled(&self) -> &[u32] {
+        &self.samples[..self.filled]
+    }
+
+    fn is_full(&self) -> bool {
+        self.filled == self.len
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Median {
+    window: Window<MAX_MEDIAN>,
+}
+
+impl Median {
+    pub fn new(n: usize) -> Self {
+        Median { window: Window::new(n) }
+    }
+}
+
+impl Filter for Median {
+    fn apply(&mut self, raw: u32) -> u32 {
+        self.window.push(raw);
+        let mut sorted = [0u32; MAX_MEDIAN];
+        let filled = self.window.filled();
+        let sorted = &mut sorted[..filled.len()];
+        sorted.copy_from_slice(filled);
+        sorted.sort_unstable();
+        sorted[sorted.len() / 2]
+    }
+
+    fn is_settled(&self) -> bool {
+        self.window.is_full()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Ema {
+    shift: u32,
+    /// Running average with `EMA_FRAC_BITS` fractional bits; starts at the
+    /// first sample.
+    average: Option<u64>,
+}
+
+impl Ema {
+    pub fn new(shift: u32) -> Self {
+        Ema { shift, average: None }
+    }
+}
+
+impl Filter for Ema {
+    fn apply(&mut self, raw: u32) -> u32 {
+        let raw = (raw as u64) << EMA_FRAC_BITS;
+        let average = self.average.get_or_insert(raw);
+        let error = raw as i64 - *average as i64;
+        *average = (*average as i64 + (error >> self.shift)) as u64;
+        // Round to nearest so a steady input comes back out unchanged
+        ((*average + (1 << (EMA_FRAC_BITS - 1))) >> EMA_FRAC_BITS) as u32
+    }
+}
+
+/// Move `value` towards `target` by `1 / 2^shift` of the difference: one
+/// step of an EMA kept in the caller's own fixed point.
+pub(crate) fn ema(value: u32, target: u32, shift: u32) -> u32 {
+    if target >= value {
+        value + ((target - value) >> shift)
+    } else {
+        value - ((value - target) >> shift)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Notch {
+    window: Window<MAX_NOTCH_PERIOD>,
+    sum: u64,
+}
+
+impl Notch {
+    pub fn new(period: usize) -> Self {
+        Notch {
+            window: Window::new(period),
+            sum: 0,
+        }
+    }
+}
+
+impl Filter for Notch {
+    fn apply(&mut self, raw: u32) -> u32 {
+        self.sum += raw as u64;
+        if let Some(old) = self.window.push(raw) {
+            self.sum -= old as u64;
+        }
+        (self.sum / self.window.filled().len() as u64) as u32
+    }
+
+    fn is_settled(&self) -> bool {
+        self.window.is_full()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Stage {
+    Median(Median),
+    Ema(Ema),
+    Notch(Notch),
+}
+
+impl From<FilterStage> for Stage {
+    fn from(stage: FilterStage) -> Self {
+        match stage {
+            FilterStage::Median(n) => Stage::Median(Median::new(n)),
+            FilterStage::Ema(shift) => Stage::Ema(Ema::new(shift)),
+            FilterStage::Notch(period) => Stage::Notch(Notch::new(period)),
+        }
+    }
+}
+
+impl Filter for Stage {
+    fn apply(&mut self, raw: u32) -> u32 {
+        match self {
+            Stage::Median(f) => f.apply(raw),
+            Stage::Ema(f) => f.apply(raw),
+            Stage::Notch(f) => f.apply(raw),
+        }
+    }
+
+    fn is_settled(&self) -> bool {
+        match self {
+            Stage::Median(f) => f.is_settled(),
+            Stage::Ema(f) => f.is_settled(),
+            Stage::Notch(f) => f.is_settled(),
+        }
+    }
+}
+
+/// Up to `MAX_STAGES` filters run in order. An empty chain passes samples
+/// through unchanged.
+#[derive(Clone, Copy, Debug)]
+pub struct FilterChain {
+    stages: [Option<Stage>; MAX_STAGES],
+}
+
+impl FilterChain {
+    pub fn new(stages: &[Option<FilterStage>; MAX_STAGES]) -> Self {
+        FilterChain {
+            stages: stages.map(|stage| stage.map(Stage::from)),
+        }
+    }
+}
+
+impl Filter for FilterChain {
+    fn apply(&mut self, raw: u32) -> u32 {
+        // A stage only starts once the stages before it have settled, so an
+        // EMA does not spend its first time constants forgetting their
+        // partial output
+        let mut value = raw;
+        for stage in self.stages.iter_mut().flatten() {
+            value = stage.apply(value);
+            if !stage.is_settled() {
+                break;
+            }
+        }
+        value
+    }
+
+    fn is_settled(&self) -> bool {
+        self.stages.iter().flatten().all(|stage| stage.is_settled())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(filter: &mut impl Filter, input: &[u32]) -> Vec<u32> {
+        input.iter().map(|&raw| filter.apply(raw)).collect()
+    }
+
+    // --- Stage validation ---
+
+    #[test]
+    fn test_stage_ranges() {
+        assert!(FilterStage::Median(3).is_valid());
+        assert!(FilterStage::Median(MAX_MEDIAN).is_valid());
+        assert!(!FilterStage::Median(1).is_valid());
+        assert!(!FilterStage::Median(4).is_valid());
+        assert!(!FilterStage::Median(MAX_MEDIAN + 2).is_valid());
+        assert!(FilterStage::Ema(1).is_valid());
+        assert!(!FilterStage::Ema(0).is_valid());
+        assert!(!FilterStage::Ema(MAX_EMA_SHIFT + 1).is_valid());
+        assert!(FilterStage::Notch(6).is_valid());
+        assert!(!FilterStage::Notch(1).is_valid());
+        assert!(!FilterStage::Notch(MAX_NOTCH_PERIOD + 1).is_valid());
+    }
+
+    // --- Sample rate ---
+
+    #[test]
+    fn test_rescale_keeps_time() {
+        assert_eq!(rescale(200, 300, 300), 200);
+        assert_eq!(rescale(200, 300, 75), 50);
+        assert_eq!(rescale(200, 300, 1200), 800);
+        assert_eq!(rescale(5, 300, 75), 1);
+        assert_eq!(rescale(1, 300, 75), 1);
+        assert_eq!(rescale(0, 300, 1200), 0);
+    }
+
+    #[test]
+    fn test_rescale_shift_to_nearest_power_of_two() {
+        assert_eq!(rescale_shift(7, 300, 300), 7);
+        assert_eq!(rescale_shift(7, 300, 400), 7);
+        assert_eq!(rescale_shift(7, 300, 450), 8);
+        assert_eq!(rescale_shift(7, 300, 1200), 9);
+        assert_eq!(rescale_shift(7, 300, 75), 5);
+        assert_eq!(rescale_shift(7, 300, 200), 6);
+    }
+
+    #[test]
+    fn test_notch_follows_mains_across_rates() {
+        let at = |period, to| FilterStage::Notch(period).at_sample_rate(300, to);
+        assert_eq!(at(6, 300), FilterStage::Notch(6));
+        // 50 Hz
+        assert_eq!(at(6, 1200), FilterStage::Notch(24));
+        assert_eq!(at(6, 600), FilterStage::Notch(12));
+        // Aliased to 25 Hz, three samples a cycle
+        assert_eq!(at(6, 75), FilterStage::Notch(3));
+        assert_eq!(at(6, 100), FilterStage::Notch(2));
+        // 60 Hz
+        assert_eq!(at(5, 1200), FilterStage::Notch(20));
+        assert_eq!(at(5, 75), FilterStage::Notch(5));
+    }
+
+    #[test]
+    fn test_rescaled_notch_nulls_hum() {
+        // 50 Hz sampled at 75/s: a sine, so no period of its own
+        let hum: Vec<u32> = (0..300)
+            .map(|i| (1000.0 + 100.0 * (i as f64 * 50.0 / 75.0 * core::f64::consts::TAU).sin()) as u32)
+            .collect();
+        let FilterStage::Notch(period) = FilterStage::Notch(6).at_sample_rate(300, 75) else {
+            unreachable!();
+        };
+        let out = run(&mut Notch::new(period), &hum);
+        assert!(out[period..].iter().all(|&v| v.abs_diff(1000) <= 1), "{:?}", &out[..20]);
+    }
+
+    #[test]
+    fn test_ema_and_median_rescale() {
+        assert_eq!(FilterStage::Ema(2).at_sample_rate(300, 1200), FilterStage::Ema(4));
+        assert_eq!(FilterStage::Ema(1).at_sample_rate(300, 75), FilterStage::Ema(1));
+        assert_eq!(FilterStage::Median(5).at_sample_rate(300, 1200), FilterStage::Median(5));
+    }
+
+    // --- Median ---
+
+    #[test]
+    fn test_median_removes_spikes() {
+        let mut median = Median::new(5);
+        let out = run(&mut median, &[1000, 1000, 1000, 5000, 1000, 5000, 1000, 1000]);
+        assert!(out.iter().all(|&v| v == 1000), "{:?}", out);
+    }
+
+    #[test]
+    fn test_median_keeps_step_edges() {
+        let mut median = Median::new(5);
+        let out = run(&mut median, &[1000, 1000, 1000, 1000, 1000, 1100, 1100, 1100, 1100]);
+        assert_eq!(out, [1000, 1000, 1000, 1000, 1000, 1000, 1000, 1100, 1100]);
+    }
+
+    // --- EMA ---
+
+    #[test]
+    fn test_ema_starts_at_first_sample_and_holds_steady() {
+        let mut ema = Ema::new(3);
+        assert_eq!(run(&mut ema, &[1000; 20]), [1000; 20]);
+    }
+
+    #[test]
+    fn test_ema_step_response() {
+        let mut ema = Ema::new(2);
+        run(&mut ema, &[1000; 4]);
+        let out = run(&mut ema, &[1100; 30]);
+        assert_eq!(out[0], 1025);
+        assert!(out.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(*out.last().unwrap(), 1100);
+    }
+
+    #[test]
+    fn test_ema_step_moves_both_ways_without_overflow() {
+        assert_eq!(ema(0, 1024, 2), 256);
+        assert_eq!(ema(1024, 0, 2), 768);
+        assert_eq!(ema(u32::MAX, 0, 1), u32::MAX - u32::MAX / 2);
+        assert_eq!(ema(0, u32::MAX, 1), u32::MAX / 2);
+        assert_eq!(ema(7, 7, 3), 7);
+    }
+
+    // --- Notch ---
+
+    #[test]
+    fn test_notch_nulls_hum_at_its_period() {
+        let hum = [0, 30, 60, 60, 30, 0];
+        let mut notch = Notch::new(6);
+        let input: Vec<u32> = (0..60).map(|i| 1000 + hum[i % 6]).collect();
+        let out = run(&mut notch, &input);
+        assert!(out[6..].iter().all(|&v| v == 1030), "{:?}", out);
+    }
+
+    #[test]
+    fn test_notch_passes_steps_after_one_period() {
+        let mut notch = Notch::new(6);
+        run(&mut notch, &[1000; 6]);
+        let out = run(&mut notch, &[1120; 6]);
+        assert_eq!(out, [1020, 1040, 1060, 1080, 1100, 1120]);
+    }
+
+    // --- Chain ---
+
+    #[test]
+    fn test_empty_chain_passes_through() {
+        let mut chain = FilterChain::new(&[None; MAX_STAGES]);
+        assert_eq!(run(&mut chain, &[1, 5000, 3]), [1, 5000, 3]);
+    }
+
+    #[test]
+    fn test_chain_runs_stages_in_order() {
+        let stages = [Some(FilterStage::Median(3)), Some(FilterStage::Ema(1)), None];
+        let mut chain = FilterChain::new(&stages);
+        // The spike never reaches the EMA
+        let out = run(&mut chain, &[1000, 1000, 9000, 1000, 1000]);
+        assert_eq!(out, [1000; 5]);
+    }
+
+    #[test]
+    fn test_chain_settles_when_every_window_is_full() {
+        let stages = [Some(FilterStage::Median(3)), Some(FilterStage::Notch(4)), None];
+        let mut chain = FilterChain::new(&stages);
+        assert!(!chain.is_settled());
+        // The notch only starts counting once the median is full
+        run(&mut chain, &[1000; 5]);
+        assert!(!chain.is_settled());
+        run(&mut chain, &[1000]);
+        assert!(chain.is_settled());
+        assert!(FilterChain::new(&[Some(FilterStage::Ema(4)), None, None]).is_settled());
+    }
+
+    #[test]
+    fn test_ema_starts_from_settled_input() {
+        let stages = [Some(FilterStage::Notch(2)), Some(FilterStage::Ema(4)), None];
+        let mut chain = FilterChain::new(&stages);
+        // The notch's one-sample average of 900 never seeds the EMA
+        assert_eq!(run(&mut chain, &[900, 1100, 900, 1100]), [900, 1000, 1000, 1000]);
+    }
+}
@@ -0,0 +1,76 @@
+use rp2040_hal::rom_data;
+use touch_switch::settings::{self, Settings, PAGE_SIZE, SECTOR_SIZE};
+
+/// Start of the memory-mapped (XIP) flash window.
+const XIP_BASE: usize = 0x1000_0000;
+/// 4K sector erase command.
+const SECTOR_ERASE_CMD: u8 = 0x20;
+
+extern "C" {
+    /// Start of the `SETTINGS` region reserved in `memory.x`.
+    static __settings_start: u8;
+}
+
+fn sector() -> &'static [u8] {
+    unsafe { core::slice::from_raw_parts(core::ptr::addr_of!(__settings_start), SECTOR_SIZE) }
+}
+
+fn sector_offset() -> u32 {
+    (sector().as_ptr() as usize - XIP_BASE) as u32
+}
+
+pub fn load() -> Option<Settings> {
+    settings::load(sector())
+}
+
+/// Program `value` into the next free record slot, erasing the sector first
+/// when it is full. Stalls the CPU for the duration of the flash operation:
+/// about 1 ms to program a page, and tens of ms more on the rare erase.
+pub fn save(value: &Settings) {
+    let slot = settings::next_free_slot(sector());
+    let data = value.page_image(slot.unwrap_or(0));
+    let rom = RomFns {
+        connect_internal_flash: rom_data::connect_internal_flash::ptr(),
+        flash_exit_xip: rom_data::flash_exit_xip::ptr(),
+        flash_range_erase: rom_data::flash_range_erase::ptr(),
+        flash_range_program: rom_data::flash_range_program::ptr(),
+        flash_flush_cache: rom_data::flash_flush_cache::ptr(),
+        flash_enter_cmd_xip: rom_data::flash_enter_cmd_xip::ptr(),
+    };
+    let offset = sector_offset();
+    let page_offset = offset + (settings::slot_page(slot.unwrap_or(0)) * PAGE_SIZE) as u32;
+    cortex_m::interrupt::free(|_| unsafe {
+        write_page(&rom, offset, slot.is_none(), page_offset, data.as_ptr());
+    });
+}
+
+/// ROM routines, looked up before XIP is disabled since the lookup code
+/// itself lives in flash.
+struct RomFns {
+    connect_internal_flash: unsafe extern "C" fn(),
+    flash_exit_xip: unsafe extern "C" fn(),
+    flash_range_erase: unsafe extern "C" fn(u32, usize, u32, u8),
+    flash_range_program: unsafe extern "C" fn(u32, *const u8, usize),
+    flash_flush_cache: unsafe extern "C" fn(),
+    flash_enter_cmd_xip: unsafe extern "C" fn(),
+}
+
+/// Runs from RAM: flash is not readable between `flash_exit_xip` and
+/// `flash_enter_cmd_xip`. The 03h XIP mode restored at the end is the same one
+/// the `BOOT_LOADER_GENERIC_03H` boot2 sets up.
+///
+/// The body must make no calls into flash, which unoptimised builds emit
+/// even for trivial helpers like `as_ptr`, so `data` (`PAGE_SIZE` bytes)
+/// comes in as a raw pointer and only the ROM routines are called.
+#[inline(never)]
+#[link_section = ".data.ram_func"]
+unsafe fn write_page(rom: &RomFns, sector_offset: u32, erase: bool, page_offset: u32, data: *const u8) {
+    (rom.connect_internal_flash)();
+    (rom.flash_exit_xip)();
+    if erase {
+        (rom.flash_range_erase)(sector_offset, SECTOR_SIZE, SECTOR_SIZE as u32, SECTOR_ERASE_CMD);
+    }
+    (rom.flash_range_program)(page_offset, data, PAGE_SIZE);
+    (rom.flash_flush_cache)();
+    (rom.flash_enter_cmd_xip)();
+}
@@ -1,3 +1,28 @@
 #![cfg_attr(not(test), no_std)]
 
+pub mod autorange;
 pub mod channel;
+pub mod clap;
+pub mod colour;
+pub mod fade;
+pub mod filter;
+pub mod light;
+pub mod onset;
+pub mod settings;
+pub mod slider;
+
+/// Discards defmt output, so the library's log statements link on the host.
+#[cfg(test)]
+mod test_logger {
+    #[defmt::global_logger]
+    struct TestLogger;
+
+    unsafe impl defmt::Logger for TestLogger {
+        fn acquire() {}
+        unsafe fn flush() {}
+        unsafe fn release() {}
+        unsafe fn write(_bytes: &[u8]) {}
+    }
+
+    defmt::timestamp!("");
+}
@@ -1,22 +1,76 @@
-use touch_switch::channel::TouchState;
-use embedded_hal::prelude::_embedded_hal_blocking_spi_Write;
-use rp2040_hal::spi::{Spi, SpiDevice, ValidSpiPinout, Enabled};
-use defmt::*;
-use defmt_rtt as _;
-
-static DIM_DIVISOR: u16 = 512;
-
-const GAMMA: [u8; 256] = {
-    let mut g = [0u8; 256];
-    let mut i = 0;
-    while i < 256 {
-        g[i] = ((i * i) / 255) as u8;
-        i += 1;
-    }
-    g
+use crate::channel::{SensorFault, TouchEvent};
+use crate::colour::{kelvin_to_rgb, Colour};
+use crate::fade::{Easing, Fade};
+use crate::slider::{Direction, SliderEvent};
+use defmt::debug;
+use embedded_hal::blocking::spi::Write;
+use smart_leds::{
+    colors::{RED, WHITE},
+    RGB8,
 };
 
-#[derive(Clone, Copy, PartialEq)]
+/// Default duration of a touch-triggered fade.
+pub const DEFAULT_FADE_MS: u32 = 3_000;
+/// Default time for a press-and-hold ramp to cover the full level range.
+pub const DEFAULT_HOLD_RAMP_MS: u32 = 4_000;
+/// Lowest level a hold ramp dims to, so dimming never switches the light off.
+const MIN_DIM_LEVEL: u8 = 1;
+/// Night mode: a dim amber glow.
+pub const NIGHT_KELVIN: u16 = 1800;
+pub const NIGHT_LEVEL: u8 = 24;
+/// Proximity: the dim glow a nearby hand wakes an off light to, and how long
+/// it lingers after the hand moves away.
+pub const GLOW_LEVEL: u8 = 12;
+pub const GLOW_LINGER_MS: u32 = 10_000;
+/// Level change for one tap on a brighter or dimmer pad.
+pub const STEP_LEVEL: u8 = 32;
+/// Fade time for one brighter or dimmer step.
+pub const STEP_FADE_MS: u32 = 300;
+/// What a recognised clap pattern does to the light.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ClapAction {
+    /// Straight off if lit, otherwise straight on.
+    Toggle,
+    /// Fade to full brightness.
+    Full,
+    /// Fade to night mode.
+    Night,
+    /// Step the level down by `STEP_LEVEL`.
+    Dim,
+    /// Fade to the scene, or off if the scene is already showing.
+    Scene(Scene),
+}
+
+/// Fault signal: the light blinks red at `FAULT_LEVEL` once per
+/// `FAULT_BLINK_MS` on and off, as many times as the fault's code, then
+/// pauses; the pattern is shown `FAULT_REPEATS` times.
+pub const FAULT_LEVEL: u8 = 64;
+pub const FAULT_BLINK_MS: u32 = 250;
+pub const FAULT_PAUSE_MS: u32 = 1_500;
+pub const FAULT_REPEATS: u32 = 3;
+
+/// Highest combined output: full 5-bit global brightness times full 8-bit PWM.
+const MAX_OUTPUT: u32 = 31 * 255;
+
+/// Map a perceptual light level to the APA102 5-bit global brightness and
+/// 8-bit PWM value.
+///
+/// The level is squared (gamma ≈ 2.0) into a linear target in
+/// `0..=MAX_OUTPUT` (~13 bits). The smallest global brightness that can reach
+/// the target is chosen, so dim levels keep most of the PWM resolution instead
+/// of rounding down to zero. Rounding is always up, which keeps
+/// `global * pwm` non-decreasing across the whole range.
+pub const fn brightness(level: u8) -> (u8, u8) {
+    let level = level as u32;
+    let target = (level * level * MAX_OUTPUT).div_ceil(255 * 255);
+    let global = target.div_ceil(255);
+    if global == 0 {
+        return (0, 0);
+    }
+    (global as u8, target.div_ceil(global) as u8)
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum LightState {
     On,
     Off,
@@ -25,127 +79,1752 @@ pub enum LightState {
     Steady,
 }
 
-pub struct Light<D: SpiDevice, P: ValidSpiPinout<D>> {
-    spi: Spi<Enabled, D, P>,
+/// What a long touch does.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TouchMode {
+    /// A long touch switches instantly on or off.
+    Toggle,
+    /// Holding ramps the level, releasing stops it, and the next hold ramps
+    /// the other way.
+    Dimmer,
+}
+
+/// A colour and level recalled by a scene pad.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Scene {
+    pub colour: Colour,
+    pub level: u8,
+}
+
+/// What a touch pad does to the light.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PadAction {
+    /// Every gesture, with long touches handled according to `TouchMode`.
+    Main,
+    /// Taps step the level up by `STEP_LEVEL`; holding ramps up to full.
+    Brighter,
+    /// Taps step the level down by `STEP_LEVEL`, down to off; holding ramps
+    /// down to the dimmest level.
+    Dimmer,
+    /// A tap fades to the scene, or off if the scene is already showing.
+    Scene(Scene),
+    /// One pad of a slider or wheel. Its own events are ignored; the
+    /// firmware feeds its level to a `Slider` instead.
+    Slider,
+}
+
+/// Pads whose faults are tracked separately, one per PIO state machine.
+pub const MAX_PADS: usize = 4;
+
+/// Number of blinks signalling `fault`.
+pub const fn blink_count(fault: SensorFault) -> u32 {
+    match fault {
+        SensorFault::Open => 1,
+        SensorFault::Shorted => 2,
+        SensorFault::Saturated => 3,
+        SensorFault::NoSamples => 4,
+    }
+}
+
+/// Fault blink pattern in progress.
+#[derive(Clone, Copy, Debug)]
+struct Blink {
+    count: u32,
+    start_us: u64,
+    lit: bool,
+}
+
+impl Blink {
+    /// Whether the LED is lit at `now_us`, or `None` once the pattern is over.
+    fn lit_at(&self, now_us: u64) -> Option<bool> {
+        let elapsed_ms = now_us.saturating_sub(self.start_us) / 1000;
+        let flashes = (self.count * 2 * FAULT_BLINK_MS) as u64;
+        let period = flashes + FAULT_PAUSE_MS as u64;
+        if elapsed_ms >= period * FAULT_REPEATS as u64 {
+            return None;
+        }
+        let t = elapsed_ms % period;
+        Some(t < flashes && (t / FAULT_BLINK_MS as u64).is_multiple_of(2))
+    }
+}
+
+/// Number of end-frame bytes needed to clock data through a chain of `pixels`
+/// APA102s: each LED delays the data by half a clock, so at least `pixels / 2`
+/// extra clock edges are required. Never less than the classic 4-byte frame.
+pub const fn end_frame_len(pixels: usize) -> usize {
+    let needed = pixels.div_ceil(16);
+    if needed > 4 {
+        needed
+    } else {
+        4
+    }
+}
+
+/// APA102 driver and fade logic, generic over any blocking SPI writer.
+///
+/// `N` is the number of chained LEDs. Each pixel holds its own colour, which
+/// is scaled by the shared light level when the strip is written.
+pub struct Light<S: Write<u8>, const N: usize = 1> {
+    spi: S,
+    pixels: [RGB8; N],
+    colour: Colour,
     state: LightState,
     light_level: u8,
-    sub_count: u16,
-    last_touch_state: TouchState,
+    fade: Option<Fade>,
+    fade_ms: u32,
+    easing: Easing,
+    mode: TouchMode,
+    hold_ramp_ms: u32,
+    hold_up: bool,
+    remembered: u8,
+    night: bool,
+    glow: bool,
+    glow_until_us: Option<u64>,
+    /// Each pad's fault, by pad index; `process` reports as pad 0.
+    faults: [Option<SensorFault>; MAX_PADS],
+    blink: Option<Blink>,
 }
 
-impl<D: SpiDevice, P: ValidSpiPinout<D>> Light<D, P> {
-    pub fn new(spi: Spi<Enabled, D, P>) -> Self {
+impl<S: Write<u8>, const N: usize> Light<S, N> {
+    pub fn new(spi: S) -> Self {
         let mut light = Light {
             spi,
+            pixels: [WHITE; N],
+            colour: Colour::Rgb(WHITE),
             state: LightState::Off,
             light_level: 0,
-            sub_count: 0,
-            last_touch_state: TouchState::Warmup,
+            fade: None,
+            fade_ms: DEFAULT_FADE_MS,
+            easing: Easing::Linear,
+            mode: TouchMode::Toggle,
+            hold_ramp_ms: DEFAULT_HOLD_RAMP_MS,
+            hold_up: false,
+            remembered: 0xff,
+            night: false,
+            glow: false,
+            glow_until_us: None,
+            faults: [None; MAX_PADS],
+            blink: None,
         };
-        light.write_led(0, 0, 0);
+        light.show();
         light
     }
 
-    fn write_led(&mut self, r: u8, g: u8, b: u8) {
-        let brightness = 0xE0 | 0x1F;
+    /// Write the whole strip: start frame, one frame per pixel, end frame.
+    /// A fault blink replaces the pixels until it is over, and night mode
+    /// shows them all amber without changing them.
+    pub fn show(&mut self) {
+        let level = match self.blink {
+            Some(Blink { lit: true, .. }) => FAULT_LEVEL,
+            Some(Blink { lit: false, .. }) => 0,
+            None => self.light_level,
+        };
+        let (global, pwm) = brightness(level);
         let start_frame = [0u8; 4];
-        let led_frame = [brightness, GAMMA[b as usize], GAMMA[g as usize], GAMMA[r as usize]];
         let end_frame = [0xFFu8; 4];
-        
+
+        let night = kelvin_to_rgb(NIGHT_KELVIN);
         self.spi.write(&start_frame).ok();
-        self.spi.write(&led_frame).ok();
-        self.spi.write(&end_frame).ok();
+        for &pixel in &self.pixels {
+            let RGB8 { r, g, b } = match (self.blink, self.night) {
+                (Some(_), _) => RED,
+                (None, true) => night,
+                (None, false) => pixel,
+            };
+            let led_frame = [0xE0 | global, scale(b, pwm), scale(g, pwm), scale(r, pwm)];
+            self.spi.write(&led_frame).ok();
+        }
+        let mut remaining = end_frame_len(N);
+        while remaining > 0 {
+            let len = remaining.min(end_frame.len());
+            self.spi.write(&end_frame[..len]).ok();
+            remaining -= len;
+        }
+    }
+
+    /// Set the colour of the whole strip, replacing any per-pixel colours,
+    /// and rewrite it at the current level. Leaves night mode.
+    pub fn set_colour(&mut self, colour: Colour) {
+        self.colour = colour;
+        self.night = false;
+        self.fill(colour.to_rgb());
+        self.show();
+    }
+
+    pub fn colour(&self) -> Colour {
+        self.colour
+    }
+
+    /// Set the colour of one pixel. Takes effect on the next write.
+    pub fn set_pixel(&mut self, index: usize, colour: RGB8) {
+        if let Some(pixel) = self.pixels.get_mut(index) {
+            *pixel = colour;
+        }
+    }
+
+    /// Set every pixel to the same colour. Takes effect on the next write.
+    pub fn fill(&mut self, colour: RGB8) {
+        self.pixels = [colour; N];
+    }
+
+    pub fn pixel(&self, index: usize) -> Option<RGB8> {
+        self.pixels.get(index).copied()
+    }
+
+    pub fn pixel_count(&self) -> usize {
+        N
     }
 
     pub fn off(&mut self) {
+        self.end_glow();
+        self.fade = None;
         self.level(0);
         self.state = LightState::Off;
+        self.leave_night();
     }
 
+    /// Switch straight to the remembered level.
     pub fn on(&mut self) {
-        self.level(0xff);
-        self.state = LightState::On;
+        self.end_glow();
+        self.fade = None;
+        self.level(self.remembered);
+        self.state = rest_state(self.remembered);
+    }
+
+    /// Last non-zero level the light came to rest at; the next "on" restores it.
+    pub fn remembered_level(&self) -> u8 {
+        self.remembered
+    }
+
+    /// Restore a remembered level, e.g. one loaded from flash at boot. Zero is
+    /// ignored, since "on" must light something.
+    pub fn set_remembered_level(&mut self, level: u8) {
+        if level > 0 {
+            self.remembered = level;
+        }
+    }
+
+    pub fn is_fading(&self) -> bool {
+        self.fade.is_some()
+    }
+
+    /// Fade to full brightness in the normal colour, leaving night mode.
+    pub fn full(&mut self, now_us: u64) {
+        self.leave_night();
+        self.show();
+        self.fade_towards(0xff, now_us);
+    }
+
+    /// Fade to a dim amber glow. Night mode lasts until the light goes off or
+    /// is set to full, and does not change the remembered level.
+    pub fn night_mode(&mut self, now_us: u64) {
+        self.night = true;
+        self.show();
+        self.fade_towards(NIGHT_LEVEL, now_us);
+    }
+
+    pub fn is_night(&self) -> bool {
+        self.night
+    }
+
+    /// The pixels show their own colours again from the next write.
+    fn leave_night(&mut self) {
+        self.night = false;
+    }
+
+    /// Set the duration and curve used by subsequent fades.
+    pub fn set_fade(&mut self, duration_ms: u32, easing: Easing) {
+        self.fade_ms = duration_ms;
+        self.easing = easing;
+    }
+
+    pub fn set_mode(&mut self, mode: TouchMode) {
+        self.mode = mode;
+    }
+
+    pub fn mode(&self) -> TouchMode {
+        self.mode
+    }
+
+    /// Set how long a press-and-hold ramp takes to cover the full range.
+    pub fn set_hold_ramp(&mut self, duration_ms: u32) {
+        self.hold_ramp_ms = duration_ms;
     }
 
     fn level(&mut self, amount: u8) {
         self.light_level = amount;
-        self.write_led(amount, amount, amount);
+        self.show();
     }
 
     pub fn current_level(&self) -> u8 {
         self.light_level
     }
 
-    pub fn process(&mut self, touch_state: TouchState) {
-        match touch_state {
-            TouchState::Idle => {
-                self.sub_count += 1;
-                if self.sub_count >= DIM_DIVISOR {
-                    match self.state {
-                        LightState::Rising => {
-                            self.increment();
-                        }
-                        LightState::Falling => {
-                            self.decrement();
-                        }
-                        LightState::Off | LightState::On | LightState::Steady => (),
-                    }
-                    self.sub_count = 0;
-                }
-            }
-            TouchState::Long => {
-                if self.last_touch_state != TouchState::Long {
-                    match self.state {
-                        LightState::Off => {
-                            debug!("Long touch ⇒ on");
-                            self.on()
-                        }
-                        LightState::On | LightState::Rising | LightState::Falling | LightState::Steady => {
-                            debug!("Long touch ⇒ off");
-                            self.off()
-                        }
-                    }
-                }
+    pub fn state(&self) -> LightState {
+        self.state
+    }
+
+    /// Handle a touch event that happened at `now_us` (microseconds, monotonic).
+    pub fn process(&mut self, event: TouchEvent, now_us: u64) {
+        if is_gesture(event) {
+            self.end_glow();
+        }
+        match event {
+            TouchEvent::LongPressStart => match self.mode {
+                TouchMode::Toggle => self.toggle(),
+                TouchMode::Dimmer => self.start_hold(now_us),
             },
-            TouchState::Short => match self.state {
+            TouchEvent::LongPressEnd | TouchEvent::Stuck => self.end_hold(now_us),
+            TouchEvent::SingleTap => match self.state {
                 LightState::Off => {
                     debug!("Short touch: Off→on");
-                    self.level(15);
-                    self.sub_count = 0;
+                    self.fade_to(self.remembered, now_us);
                     self.state = LightState::Rising
                 }
-                LightState::On => {
+                LightState::On | LightState::Steady => {
                     debug!("Short touch: On→off");
-                    self.level(0x7f);
-                    self.sub_count = 0;
+                    self.fade_to(0, now_us);
                     self.state = LightState::Falling
                 }
-                LightState::Rising | LightState::Falling | LightState::Steady => (),
+                LightState::Rising | LightState::Falling => (),
             },
-            TouchState::Warmup => (),
+            TouchEvent::DoubleTap => self.full(now_us),
+            TouchEvent::TripleTap => self.night_mode(now_us),
+            TouchEvent::Approach => self.start_glow(now_us),
+            TouchEvent::Leave => {
+                if self.glow {
+                    self.glow_until_us = Some(now_us + GLOW_LINGER_MS as u64 * 1000);
+                }
+            }
+            TouchEvent::Fault(fault) => self.start_fault(0, fault, now_us),
+            TouchEvent::Recovered => self.end_fault(0),
+            TouchEvent::Press | TouchEvent::Release | TouchEvent::Tap | TouchEvent::HoldRepeat => (),
         }
-        self.last_touch_state = touch_state;
+        self.tick(now_us);
     }
 
-    fn increment(&mut self) {
-        let newval = match self.light_level.checked_add(1) {
-            Some(val) => val,
-            None => {
-                self.state = LightState::On;
-                u8::MAX
+    /// Handle a touch event from pad number `pad` (below `MAX_PADS`), mapped
+    /// to `action`.
+    pub fn process_pad(&mut self, pad: usize, action: PadAction, event: TouchEvent, now_us: u64) {
+        if is_gesture(event) {
+            self.end_glow();
+        }
+        let up = action == PadAction::Brighter;
+        match (action, event) {
+            (_, TouchEvent::Fault(fault)) => self.start_fault(pad, fault, now_us),
+            (_, TouchEvent::Recovered) => self.end_fault(pad),
+            (PadAction::Main, event) => return self.process(event, now_us),
+            (
+                PadAction::Brighter | PadAction::Dimmer,
+                TouchEvent::SingleTap | TouchEvent::DoubleTap | TouchEvent::TripleTap,
+            ) => self.step(up, now_us),
+            (PadAction::Brighter | PadAction::Dimmer, TouchEvent::LongPressStart) => self.ramp(up, now_us),
+            (PadAction::Brighter | PadAction::Dimmer, TouchEvent::LongPressEnd | TouchEvent::Stuck) => {
+                self.stop_ramp(now_us)
             }
+            (PadAction::Scene(scene), TouchEvent::SingleTap) => self.scene(scene, now_us),
+            _ => (),
+        }
+        self.tick(now_us);
+    }
+
+    /// Handle a slider or wheel event: the finger position is the level, and
+    /// a swipe goes to full or off.
+    pub fn process_slider(&mut self, event: SliderEvent, now_us: u64) {
+        self.end_glow();
+        match event {
+            SliderEvent::Position(position) => {
+                self.fade = None;
+                let level = position.max(MIN_DIM_LEVEL);
+                self.level(level);
+                self.state = rest_state(level);
+            }
+            SliderEvent::Release => self.remember(),
+            SliderEvent::Swipe(Direction::Forward) => self.full(now_us),
+            SliderEvent::Swipe(Direction::Backward) => self.fade_towards(0, now_us),
+        }
+        self.tick(now_us);
+    }
+
+    /// Handle a recognised clap pattern mapped to `action`.
+    pub fn process_clap(&mut self, action: ClapAction, now_us: u64) {
+        // The glow counts as off, like it does for a touch
+        let lit = self.light_level > 0 && !self.glow;
+        self.end_glow();
+        match action {
+            ClapAction::Toggle => {
+                if lit {
+                    self.off()
+                } else {
+                    self.on()
+                }
+            }
+            ClapAction::Full => self.full(now_us),
+            ClapAction::Night => self.night_mode(now_us),
+            ClapAction::Dim => self.step(false, now_us),
+            ClapAction::Scene(scene) => self.scene(scene, now_us),
+        }
+        self.tick(now_us);
+    }
+
+    /// Step one `STEP_LEVEL` up or down from where the light is heading, so
+    /// quick taps add up.
+    fn step(&mut self, up: bool, now_us: u64) {
+        let from = self.fade.map_or(self.light_level, |fade| fade.target());
+        let target = if up {
+            from.saturating_add(STEP_LEVEL)
+        } else {
+            from.saturating_sub(STEP_LEVEL)
         };
-        self.level(newval);
+        self.fade_over(target, STEP_FADE_MS, now_us);
+    }
+
+    fn scene(&mut self, scene: Scene, now_us: u64) {
+        let showing = self.colour == scene.colour && !self.night && self.light_level == scene.level;
+        if showing && !self.is_fading() {
+            self.fade_towards(0, now_us);
+        } else {
+            self.set_colour(scene.colour);
+            self.fade_towards(scene.level, now_us);
+        }
     }
 
-    fn decrement(&mut self) {
-        let newval = match self.light_level.checked_sub(1) {
-            Some(val) => val,
-            None => {
+    /// Wake an off light to `GLOW_LEVEL`. The light still counts as off for
+    /// touches, and the glow is never remembered.
+    fn start_glow(&mut self, now_us: u64) {
+        if self.state == LightState::Off || self.glow {
+            self.glow = true;
+            self.glow_until_us = None;
+            self.fade_over(GLOW_LEVEL, STEP_FADE_MS, now_us);
+        }
+    }
+
+    /// A gesture takes over from the glow, acting as if the light were off.
+    fn end_glow(&mut self) {
+        if self.glow {
+            self.glow = false;
+            self.glow_until_us = None;
+            self.fade = None;
+            self.state = LightState::Off;
+        }
+    }
+
+    pub fn is_glowing(&self) -> bool {
+        self.glow
+    }
+
+    /// Safe fallback for a faulty pad, which can no longer end a hold or a
+    /// glow: stop any fade or ramp where it is, let a glow fade out, and
+    /// blink the fault's code.
+    fn start_fault(&mut self, pad: usize, fault: SensorFault, now_us: u64) {
+        self.tick(now_us);
+        if self.glow {
+            self.glow_until_us = Some(now_us);
+        } else if self.fade.take().is_some() {
+            self.state = rest_state(self.light_level);
+            self.remember();
+        }
+        self.faults[pad] = Some(fault);
+        self.blink = Some(Blink {
+            count: blink_count(fault),
+            start_us: now_us,
+            lit: false,
+        });
+    }
+
+    /// The blink keeps going while any other pad is still faulty.
+    fn end_fault(&mut self, pad: usize) {
+        self.faults[pad] = None;
+        if self.fault().is_none() && self.blink.take().is_some() {
+            self.show();
+        }
+    }
+
+    /// The fault of the lowest-numbered faulty pad, until every pad has
+    /// recovered.
+    pub fn fault(&self) -> Option<SensorFault> {
+        self.faults.iter().find_map(|&fault| fault)
+    }
+
+    pub fn is_blinking(&self) -> bool {
+        self.blink.is_some()
+    }
+
+    fn toggle(&mut self) {
+        match self.state {
+            LightState::Off => {
+                debug!("Long touch ⇒ on");
+                self.on()
+            }
+            LightState::On | LightState::Rising | LightState::Falling | LightState::Steady => {
+                debug!("Long touch ⇒ off");
+                self.off()
+            }
+        }
+    }
+
+    /// Start a press-and-hold ramp, reversing the direction of the last one
+    /// unless the level is already at that end of the range.
+    fn start_hold(&mut self, now_us: u64) {
+        let up = match self.light_level {
+            0..=MIN_DIM_LEVEL => true,
+            0xff => false,
+            _ => !self.hold_up,
+        };
+        self.hold_up = up;
+        self.ramp(up, now_us);
+    }
+
+    /// Ramp linearly to full or to the dimmest level at the hold-ramp rate.
+    fn ramp(&mut self, up: bool, now_us: u64) {
+        self.level(self.light_level.max(MIN_DIM_LEVEL));
+        let target = if up { 0xff } else { MIN_DIM_LEVEL };
+        let distance = target.abs_diff(self.light_level) as u32;
+        let duration_ms = self.hold_ramp_ms * distance / 0xff;
+        self.fade = Some(Fade::new(self.light_level, target, now_us, duration_ms, Easing::Linear));
+        self.state = if up { LightState::Rising } else { LightState::Falling };
+    }
+
+    /// Stop a press-and-hold ramp where it is.
+    fn end_hold(&mut self, now_us: u64) {
+        if self.mode == TouchMode::Dimmer {
+            self.stop_ramp(now_us);
+        }
+    }
+
+    fn stop_ramp(&mut self, now_us: u64) {
+        self.tick(now_us);
+        if self.fade.take().is_some() {
+            self.state = LightState::Steady;
+            self.remember();
+        }
+    }
+
+    /// Advance any running fade to `now_us`. Only writes the strip when the
+    /// level actually changes, so it is cheap to call on every loop.
+    pub fn tick(&mut self, now_us: u64) {
+        if let Some(blink) = self.blink {
+            let lit = blink.lit_at(now_us);
+            if lit != Some(blink.lit) {
+                self.blink = lit.map(|lit| Blink { lit, ..blink });
+                self.show();
+            }
+        }
+        if self.glow_until_us.is_some_and(|until| now_us >= until) {
+            self.glow_until_us = None;
+            self.fade_over(0, STEP_FADE_MS, now_us);
+        }
+        let Some(fade) = self.fade else {
+            return;
+        };
+        let level = fade.level(now_us);
+        if level != self.light_level {
+            self.level(level);
+        }
+        if fade.is_done(now_us) {
+            self.fade = None;
+            if self.glow {
+                // Glowing counts as off until the glow is over
                 self.state = LightState::Off;
-                0
+                self.glow = level > 0;
+                return;
             }
+            self.state = rest_state(level);
+            self.remember();
+            if level == 0 {
+                self.leave_night();
+            }
+        }
+    }
+
+    fn remember(&mut self) {
+        if !self.night {
+            self.set_remembered_level(self.light_level);
+        }
+    }
+
+    fn fade_to(&mut self, target: u8, now_us: u64) {
+        self.fade = Some(Fade::new(self.light_level, target, now_us, self.fade_ms, self.easing));
+    }
+
+    /// Fade to `target`, setting the state from the direction of travel.
+    fn fade_towards(&mut self, target: u8, now_us: u64) {
+        self.fade_over(target, self.fade_ms, now_us);
+    }
+
+    /// `fade_towards` with an explicit duration.
+    fn fade_over(&mut self, target: u8, duration_ms: u32, now_us: u64) {
+        self.fade = Some(Fade::new(self.light_level, target, now_us, duration_ms, self.easing));
+        self.state = if target >= self.light_level {
+            LightState::Rising
+        } else {
+            LightState::Falling
         };
-        self.level(newval);
+    }
+}
+
+/// Events that act on the light, as opposed to reporting touch progress.
+fn is_gesture(event: TouchEvent) -> bool {
+    matches!(
+        event,
+        TouchEvent::SingleTap | TouchEvent::DoubleTap | TouchEvent::TripleTap | TouchEvent::LongPressStart
+    )
+}
+
+fn rest_state(level: u8) -> LightState {
+    match level {
+        0 => LightState::Off,
+        0xff => LightState::On,
+        _ => LightState::Steady,
+    }
+}
+
+/// Scale a colour component by the PWM value, both in 0..=255.
+fn scale(component: u8, pwm: u8) -> u8 {
+    (component as u16 * pwm as u16 / 255) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::Infallible;
+
+    /// Records every byte written over "SPI".
+    #[derive(Default)]
+    struct MockSpi {
+        bytes: Vec<u8>,
+    }
+
+    impl Write<u8> for MockSpi {
+        type Error = Infallible;
+
+        fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+            self.bytes.extend_from_slice(words);
+            Ok(())
+        }
+    }
+
+    /// Expected APA102 transfer for a single LED showing grey level `v`.
+    fn frame(v: u8) -> Vec<u8> {
+        let (global, pwm) = brightness(v);
+        vec![0, 0, 0, 0, 0xE0 | global, pwm, pwm, pwm, 0xFF, 0xFF, 0xFF, 0xFF]
+    }
+
+    fn light() -> Light<MockSpi> {
+        let mut light = Light::new(MockSpi::default());
+        light.spi.bytes.clear();
+        light
+    }
+
+    fn strip<const N: usize>() -> Light<MockSpi, N> {
+        let mut light = Light::new(MockSpi::default());
+        light.spi.bytes.clear();
+        light
+    }
+
+    /// Monotonic microsecond clock driven by the test.
+    #[derive(Default)]
+    struct FakeClock {
+        now_us: u64,
+    }
+
+    impl FakeClock {
+        fn advance_ms(&mut self, ms: u64) -> u64 {
+            self.now_us += ms * 1000;
+            self.now_us
+        }
+    }
+
+    const HALF_FADE_MS: u64 = DEFAULT_FADE_MS as u64 / 2;
+
+    // --- Construction ---
+
+    #[test]
+    fn test_new_writes_black_frame() {
+        let light: Light<_> = Light::new(MockSpi::default());
+        assert_eq!(light.spi.bytes, frame(0));
+        assert_eq!(light.state(), LightState::Off);
+    }
+
+    // --- Instant on/off ---
+
+    #[test]
+    fn test_on_writes_full_frame() {
+        let mut light = light();
+        light.on();
+        assert_eq!(light.spi.bytes, frame(0xff));
+        assert_eq!(light.spi.bytes[5..8], [0xFF, 0xFF, 0xFF]);
+        assert_eq!(light.state(), LightState::On);
+    }
+
+    #[test]
+    fn test_off_writes_black_frame() {
+        let mut light = light();
+        light.on();
+        light.spi.bytes.clear();
+        light.off();
+        assert_eq!(light.spi.bytes, frame(0));
+        assert_eq!(light.state(), LightState::Off);
+    }
+
+    #[test]
+    fn test_long_press_toggles_once_per_hold() {
+        let mut light = light();
+        light.process(TouchEvent::LongPressStart, 0);
+        assert_eq!(light.spi.bytes, frame(0xff));
+        // Held: no further frames until the next long press
+        light.process(TouchEvent::HoldRepeat, 1_000);
+        assert_eq!(light.spi.bytes, frame(0xff));
+
+        light.process(TouchEvent::LongPressEnd, 2_000);
+        light.spi.bytes.clear();
+        light.process(TouchEvent::LongPressStart, 3_000);
+        assert_eq!(light.spi.bytes, frame(0));
+        assert_eq!(light.state(), LightState::Off);
+    }
+
+    #[test]
+    fn test_long_press_cancels_fade() {
+        let mut light = light();
+        let mut clock = FakeClock::default();
+        light.process(TouchEvent::SingleTap, clock.now_us);
+        light.process(TouchEvent::LongPressStart, clock.advance_ms(100));
+        assert_eq!(light.state(), LightState::Off);
+        light.spi.bytes.clear();
+        light.tick(clock.advance_ms(HALF_FADE_MS));
+        assert!(light.spi.bytes.is_empty());
+    }
+
+    // --- Rising ---
+
+    #[test]
+    fn test_single_tap_from_off_starts_rising_from_zero() {
+        let mut light = light();
+        light.process(TouchEvent::SingleTap, 0);
+        assert!(light.spi.bytes.is_empty());
+        assert_eq!(light.current_level(), 0);
+        assert_eq!(light.state(), LightState::Rising);
+    }
+
+    #[test]
+    fn test_rising_is_driven_by_time_not_samples() {
+        let mut light = light();
+        let mut clock = FakeClock::default();
+        light.process(TouchEvent::SingleTap, clock.now_us);
+        light.spi.bytes.clear();
+
+        // Any number of events at the same instant leaves the level alone
+        for _ in 0..10_000 {
+            light.process(TouchEvent::HoldRepeat, clock.now_us);
+        }
+        assert!(light.spi.bytes.is_empty());
+
+        light.tick(clock.advance_ms(HALF_FADE_MS));
+        assert_eq!(light.spi.bytes, frame(127));
+    }
+
+    #[test]
+    fn test_rising_ends_on_at_full() {
+        let mut light = light();
+        let mut clock = FakeClock::default();
+        light.process(TouchEvent::SingleTap, clock.now_us);
+        light.tick(clock.advance_ms(DEFAULT_FADE_MS as u64 - 1));
+        assert_eq!(light.state(), LightState::Rising);
+
+        light.spi.bytes.clear();
+        light.tick(clock.advance_ms(1));
+        assert_eq!(light.spi.bytes, frame(0xff));
+        assert_eq!(light.state(), LightState::On);
+    }
+
+    #[test]
+    fn test_tick_only_writes_on_level_change() {
+        let mut light = light();
+        light.process(TouchEvent::SingleTap, 0);
+        light.spi.bytes.clear();
+        // 3 s over 255 levels is ~11.8 ms per level
+        light.tick(1_000);
+        assert!(light.spi.bytes.is_empty());
+        light.tick(12_000);
+        assert_eq!(light.spi.bytes, frame(1));
+    }
+
+    // --- Falling ---
+
+    #[test]
+    fn test_single_tap_from_on_falls_from_current_level() {
+        let mut light = light();
+        let mut clock = FakeClock::default();
+        light.on();
+        light.spi.bytes.clear();
+        light.process(TouchEvent::SingleTap, clock.now_us);
+        assert!(light.spi.bytes.is_empty());
+        assert_eq!(light.state(), LightState::Falling);
+
+        light.tick(clock.advance_ms(HALF_FADE_MS));
+        assert_eq!(light.spi.bytes, frame(128));
+    }
+
+    #[test]
+    fn test_falling_ends_off_at_zero() {
+        let mut light = light();
+        let mut clock = FakeClock::default();
+        light.on();
+        light.process(TouchEvent::SingleTap, clock.now_us);
+        light.spi.bytes.clear();
+        light.tick(clock.advance_ms(DEFAULT_FADE_MS as u64));
+        assert_eq!(light.spi.bytes, frame(0));
+        assert_eq!(light.state(), LightState::Off);
+    }
+
+    #[test]
+    fn test_single_tap_ignored_while_fading() {
+        let mut light = light();
+        light.process(TouchEvent::SingleTap, 0);
+        light.spi.bytes.clear();
+        light.process(TouchEvent::SingleTap, 0);
+        assert!(light.spi.bytes.is_empty());
+        assert_eq!(light.state(), LightState::Rising);
+    }
+
+    // --- Fade configuration ---
+
+    #[test]
+    fn test_set_fade_duration() {
+        let mut light = light();
+        let mut clock = FakeClock::default();
+        light.set_fade(500, Easing::Linear);
+        light.process(TouchEvent::SingleTap, clock.now_us);
+        light.tick(clock.advance_ms(250));
+        assert_eq!(light.current_level(), 127);
+        light.tick(clock.advance_ms(250));
+        assert_eq!(light.state(), LightState::On);
+    }
+
+    #[test]
+    fn test_set_fade_easing() {
+        let mut clock = FakeClock::default();
+        let mut ease = light();
+        ease.set_fade(1_000, Easing::EaseInOut);
+        let mut exp = light();
+        exp.set_fade(1_000, Easing::Exponential);
+        ease.process(TouchEvent::SingleTap, clock.now_us);
+        exp.process(TouchEvent::SingleTap, clock.now_us);
+
+        let now = clock.advance_ms(100);
+        ease.tick(now);
+        exp.tick(now);
+        // Both start slower than the 25 levels a linear fade covers by now
+        assert!(ease.current_level() < 25);
+        assert!(exp.current_level() < 25);
+
+        let now = clock.advance_ms(400);
+        ease.tick(now);
+        exp.tick(now);
+        assert_eq!(ease.current_level(), 127);
+        assert_eq!(exp.current_level(), 7);
+    }
+
+    // --- Dimmer mode ---
+
+    fn dimmer() -> Light<MockSpi> {
+        let mut light = light();
+        light.set_mode(TouchMode::Dimmer);
+        light
+    }
+
+    /// Hold for `ms`, then release.
+    fn hold(light: &mut Light<MockSpi>, clock: &mut FakeClock, ms: u64) {
+        light.process(TouchEvent::LongPressStart, clock.now_us);
+        light.process(TouchEvent::HoldRepeat, clock.advance_ms(ms));
+        light.process(TouchEvent::LongPressEnd, clock.advance_ms(1));
+    }
+
+    #[test]
+    fn test_default_mode_is_toggle() {
+        assert_eq!(light().mode(), TouchMode::Toggle);
+    }
+
+    #[test]
+    fn test_hold_from_off_ramps_up_and_stops_on_release() {
+        let mut light = dimmer();
+        let mut clock = FakeClock::default();
+        light.process(TouchEvent::LongPressStart, clock.now_us);
+        assert_eq!(light.state(), LightState::Rising);
+        assert_eq!(light.current_level(), MIN_DIM_LEVEL);
+
+        // 4 s for 254 levels
+        light.process(TouchEvent::HoldRepeat, clock.advance_ms(2_000));
+        assert_eq!(light.current_level(), 128);
+
+        light.process(TouchEvent::LongPressEnd, clock.now_us);
+        assert_eq!(light.state(), LightState::Steady);
+        light.tick(clock.advance_ms(1_000));
+        assert_eq!(light.current_level(), 128);
+    }
+
+    #[test]
+    fn test_stuck_touch_stops_hold_ramp() {
+        let mut light = dimmer();
+        let mut clock = FakeClock::default();
+        light.process(TouchEvent::LongPressStart, clock.now_us);
+        light.process(TouchEvent::Stuck, clock.advance_ms(2_000));
+        assert_eq!(light.state(), LightState::Steady);
+        light.tick(clock.advance_ms(10_000));
+        assert_eq!(light.current_level(), 128);
+    }
+
+    #[test]
+    fn test_next_hold_reverses_direction() {
+        let mut light = dimmer();
+        let mut clock = FakeClock::default();
+        hold(&mut light, &mut clock, 2_000);
+        let level = light.current_level();
+
+        light.process(TouchEvent::LongPressStart, clock.advance_ms(500));
+        assert_eq!(light.state(), LightState::Falling);
+        light.process(TouchEvent::HoldRepeat, clock.advance_ms(1_000));
+        assert!(light.current_level() < level);
+        light.process(TouchEvent::LongPressEnd, clock.now_us);
+        let lower = light.current_level();
+
+        hold(&mut light, &mut clock, 500);
+        assert!(light.current_level() > lower);
+        assert_eq!(light.state(), LightState::Steady);
+    }
+
+    #[test]
+    fn test_hold_at_full_ramps_down() {
+        let mut light = dimmer();
+        light.on();
+        light.process(TouchEvent::LongPressStart, 0);
+        assert_eq!(light.state(), LightState::Falling);
+    }
+
+    #[test]
+    fn test_hold_down_stops_at_minimum_not_off() {
+        let mut light = dimmer();
+        let mut clock = FakeClock::default();
+        light.on();
+        hold(&mut light, &mut clock, 10_000);
+        assert_eq!(light.current_level(), MIN_DIM_LEVEL);
+        assert_eq!(light.state(), LightState::Steady);
+
+        // At the bottom the next hold goes up, whatever the last direction
+        light.process(TouchEvent::LongPressStart, clock.advance_ms(500));
+        assert_eq!(light.state(), LightState::Rising);
+    }
+
+    #[test]
+    fn test_hold_up_to_full_ends_on() {
+        let mut light = dimmer();
+        let mut clock = FakeClock::default();
+        hold(&mut light, &mut clock, 10_000);
+        assert_eq!(light.current_level(), 0xff);
+        assert_eq!(light.state(), LightState::On);
+    }
+
+    #[test]
+    fn test_single_tap_from_steady_fades_off() {
+        let mut light = dimmer();
+        let mut clock = FakeClock::default();
+        hold(&mut light, &mut clock, 1_000);
+        light.process(TouchEvent::SingleTap, clock.advance_ms(500));
+        assert_eq!(light.state(), LightState::Falling);
+        light.tick(clock.advance_ms(DEFAULT_FADE_MS as u64));
+        assert_eq!(light.state(), LightState::Off);
+    }
+
+    #[test]
+    fn test_set_hold_ramp() {
+        let mut light = dimmer();
+        let mut clock = FakeClock::default();
+        light.set_hold_ramp(1_000);
+        hold(&mut light, &mut clock, 1_000);
+        assert_eq!(light.current_level(), 0xff);
+    }
+
+    #[test]
+    fn test_toggle_mode_release_does_not_stop_fade() {
+        let mut light = light();
+        let mut clock = FakeClock::default();
+        light.process(TouchEvent::SingleTap, clock.now_us);
+        light.process(TouchEvent::LongPressStart, clock.advance_ms(10));
+        light.process(TouchEvent::LongPressEnd, clock.advance_ms(10));
+        assert_eq!(light.state(), LightState::Off);
+    }
+
+    // --- Multi-tap ---
+
+    #[test]
+    fn test_raw_tap_is_ignored() {
+        let mut light = light();
+        light.process(TouchEvent::Tap, 0);
+        assert!(!light.is_fading());
+        assert_eq!(light.state(), LightState::Off);
+    }
+
+    #[test]
+    fn test_double_tap_fades_to_full() {
+        let mut light = light();
+        let mut clock = FakeClock::default();
+        light.set_remembered_level(40);
+        light.on();
+        light.process(TouchEvent::DoubleTap, clock.now_us);
+        assert_eq!(light.state(), LightState::Rising);
+        light.tick(clock.advance_ms(DEFAULT_FADE_MS as u64));
+        assert_eq!(light.current_level(), 0xff);
+        assert_eq!(light.state(), LightState::On);
+    }
+
+    #[test]
+    fn test_triple_tap_fades_to_night_glow() {
+        let mut light = light();
+        let mut clock = FakeClock::default();
+        light.on();
+        light.process(TouchEvent::TripleTap, clock.now_us);
+        assert!(light.is_night());
+        assert_eq!(light.state(), LightState::Falling);
+        let RGB8 { r, g, b } = kelvin_to_rgb(NIGHT_KELVIN);
+        let (_, pwm) = brightness(0xff);
+        let bytes = &light.spi.bytes;
+        assert_eq!(bytes[bytes.len() - 7..bytes.len() - 4], [scale(b, pwm), scale(g, pwm), scale(r, pwm)]);
+        light.tick(clock.advance_ms(DEFAULT_FADE_MS as u64));
+        assert_eq!(light.current_level(), NIGHT_LEVEL);
+        // The night level is not the user's chosen level
+        assert_eq!(light.remembered_level(), 0xff);
+    }
+
+    #[test]
+    fn test_night_mode_ends_when_off() {
+        let mut light = light();
+        let mut clock = FakeClock::default();
+        light.process(TouchEvent::TripleTap, clock.now_us);
+        light.tick(clock.advance_ms(DEFAULT_FADE_MS as u64));
+        light.process(TouchEvent::SingleTap, clock.advance_ms(100));
+        light.tick(clock.advance_ms(DEFAULT_FADE_MS as u64));
+        assert_eq!(light.state(), LightState::Off);
+        assert!(!light.is_night());
+        assert_eq!(light.pixel(0), Some(WHITE));
+    }
+
+    #[test]
+    fn test_double_tap_leaves_night_mode() {
+        let mut light = light();
+        light.set_colour(Colour::Temperature(4000));
+        light.process(TouchEvent::TripleTap, 0);
+        light.process(TouchEvent::DoubleTap, 1_000);
+        assert!(!light.is_night());
+        assert_eq!(light.pixel(0), Some(kelvin_to_rgb(4000)));
+    }
+
+    // --- Remembered level ---
+
+    #[test]
+    fn test_remembered_level_defaults_to_full() {
+        let mut light = light();
+        assert_eq!(light.remembered_level(), 0xff);
+        light.on();
+        assert_eq!(light.current_level(), 0xff);
+    }
+
+    #[test]
+    fn test_hold_release_is_remembered_and_restored() {
+        let mut light = dimmer();
+        let mut clock = FakeClock::default();
+        hold(&mut light, &mut clock, 2_000);
+        assert_eq!(light.remembered_level(), 128);
+
+        light.process(TouchEvent::SingleTap, clock.advance_ms(100));
+        light.tick(clock.advance_ms(DEFAULT_FADE_MS as u64));
+        assert_eq!(light.state(), LightState::Off);
+        // Fading off does not overwrite the remembered level
+        assert_eq!(light.remembered_level(), 128);
+
+        light.process(TouchEvent::SingleTap, clock.advance_ms(100));
+        light.tick(clock.advance_ms(HALF_FADE_MS));
+        assert_eq!(light.current_level(), 64);
+        assert_eq!(light.state(), LightState::Rising);
+        light.tick(clock.advance_ms(HALF_FADE_MS));
+        assert_eq!(light.current_level(), 128);
+        assert_eq!(light.state(), LightState::Steady);
+    }
+
+    #[test]
+    fn test_on_restores_remembered_level() {
+        let mut light = light();
+        light.set_remembered_level(40);
+        light.on();
+        assert_eq!(light.spi.bytes, frame(40));
+        assert_eq!(light.state(), LightState::Steady);
+    }
+
+    #[test]
+    fn test_long_press_toggle_restores_remembered_level() {
+        let mut light = light();
+        light.set_remembered_level(90);
+        light.process(TouchEvent::LongPressStart, 0);
+        assert_eq!(light.current_level(), 90);
+    }
+
+    #[test]
+    fn test_set_remembered_level_ignores_zero() {
+        let mut light = light();
+        light.set_remembered_level(0);
+        assert_eq!(light.remembered_level(), 0xff);
+    }
+
+    #[test]
+    fn test_is_fading() {
+        let mut light = light();
+        assert!(!light.is_fading());
+        light.process(TouchEvent::SingleTap, 0);
+        assert!(light.is_fading());
+        light.tick(DEFAULT_FADE_MS as u64 * 1000);
+        assert!(!light.is_fading());
+    }
+
+    // --- Pad actions ---
+
+    const STEP_US: u64 = STEP_FADE_MS as u64 * 1000;
+
+    const READING: Scene = Scene {
+        colour: Colour::Temperature(4000),
+        level: 200,
+    };
+
+    #[test]
+    fn test_main_pad_is_process() {
+        let mut light = light();
+        light.process_pad(0, PadAction::Main, TouchEvent::LongPressStart, 0);
+        assert_eq!(light.state(), LightState::On);
+        light.process_pad(0, PadAction::Main, TouchEvent::DoubleTap, 0);
+        assert_eq!(light.state(), LightState::Rising);
+    }
+
+    #[test]
+    fn test_brighter_pad_steps_up() {
+        let mut light = light();
+        light.process_pad(0, PadAction::Brighter, TouchEvent::SingleTap, 0);
+        assert_eq!(light.state(), LightState::Rising);
+        light.tick(STEP_US);
+        assert_eq!(light.current_level(), STEP_LEVEL);
+        assert_eq!(light.state(), LightState::Steady);
+    }
+
+    #[test]
+    fn test_quick_steps_add_up() {
+        let mut light = light();
+        let mut clock = FakeClock::default();
+        for _ in 0..3 {
+            light.process_pad(0, PadAction::Brighter, TouchEvent::SingleTap, clock.advance_ms(50));
+        }
+        light.tick(clock.advance_ms(1_000));
+        assert_eq!(light.current_level(), 3 * STEP_LEVEL);
+    }
+
+    #[test]
+    fn test_steps_saturate() {
+        let mut light = light();
+        light.on();
+        light.process_pad(0, PadAction::Brighter, TouchEvent::SingleTap, 0);
+        light.tick(STEP_US);
+        assert_eq!(light.current_level(), 0xff);
+
+        light.set_remembered_level(STEP_LEVEL + 1);
+        light.on();
+        light.process_pad(0, PadAction::Dimmer, TouchEvent::SingleTap, 0);
+        light.process_pad(0, PadAction::Dimmer, TouchEvent::SingleTap, 0);
+        light.tick(STEP_US);
+        assert_eq!(light.current_level(), 0);
+        assert_eq!(light.state(), LightState::Off);
+    }
+
+    #[test]
+    fn test_brighter_and_dimmer_holds_ramp_one_way() {
+        let mut light = light();
+        let mut clock = FakeClock::default();
+        light.set_remembered_level(128);
+        light.on();
+        // Unlike the main pad in dimmer mode, the direction never reverses
+        for _ in 0..2 {
+            light.process_pad(0, PadAction::Dimmer, TouchEvent::LongPressStart, clock.advance_ms(10));
+            assert_eq!(light.state(), LightState::Falling);
+            light.process_pad(0, PadAction::Dimmer, TouchEvent::LongPressEnd, clock.advance_ms(500));
+            assert_eq!(light.state(), LightState::Steady);
+        }
+        // Two half-second ramps of ~32 levels each
+        let level = light.current_level();
+        assert!((62..=68).contains(&level), "level: {}", level);
+
+        light.process_pad(0, PadAction::Brighter, TouchEvent::LongPressStart, clock.advance_ms(10));
+        light.process_pad(0, PadAction::Brighter, TouchEvent::HoldRepeat, clock.advance_ms(DEFAULT_HOLD_RAMP_MS as u64));
+        assert_eq!(light.current_level(), 0xff);
+        light.process_pad(0, PadAction::Brighter, TouchEvent::LongPressEnd, clock.advance_ms(10));
+        assert_eq!(light.state(), LightState::On);
+    }
+
+    #[test]
+    fn test_scene_pad_recalls_then_switches_off() {
+        let mut light = light();
+        let mut clock = FakeClock::default();
+        light.process_pad(0, PadAction::Scene(READING), TouchEvent::SingleTap, clock.now_us);
+        light.tick(clock.advance_ms(DEFAULT_FADE_MS as u64));
+        assert_eq!(light.colour(), READING.colour);
+        assert_eq!(light.current_level(), READING.level);
+
+        light.process_pad(0, PadAction::Scene(READING), TouchEvent::SingleTap, clock.advance_ms(10));
+        light.tick(clock.advance_ms(DEFAULT_FADE_MS as u64));
+        assert_eq!(light.state(), LightState::Off);
+    }
+
+    #[test]
+    fn test_scene_pad_ignores_other_gestures() {
+        let mut light = light();
+        for event in [TouchEvent::LongPressStart, TouchEvent::DoubleTap, TouchEvent::TripleTap] {
+            light.process_pad(0, PadAction::Scene(READING), event, 0);
+            assert_eq!(light.state(), LightState::Off);
+        }
+    }
+
+    // --- Proximity glow ---
+
+    const GLOW_US: u64 = STEP_FADE_MS as u64 * 1000;
+
+    #[test]
+    fn test_approach_wakes_off_light_to_glow() {
+        let mut light = light();
+        light.process(TouchEvent::Approach, 0);
+        light.tick(GLOW_US);
+        assert_eq!(light.current_level(), GLOW_LEVEL);
+        assert_eq!(light.state(), LightState::Off);
+        assert!(light.is_glowing());
+        assert_eq!(light.remembered_level(), 0xff);
+    }
+
+    #[test]
+    fn test_glow_lingers_after_leave() {
+        let mut light = light();
+        let mut clock = FakeClock::default();
+        light.process(TouchEvent::Approach, clock.now_us);
+        light.process(TouchEvent::Leave, clock.advance_ms(1_000));
+        light.tick(clock.advance_ms(GLOW_LINGER_MS as u64 - 1));
+        assert_eq!(light.current_level(), GLOW_LEVEL);
+        light.tick(clock.advance_ms(1));
+        light.tick(clock.advance_ms(STEP_FADE_MS as u64));
+        assert_eq!(light.current_level(), 0);
+        assert!(!light.is_glowing());
+        assert_eq!(light.state(), LightState::Off);
+    }
+
+    #[test]
+    fn test_approach_again_keeps_glow() {
+        let mut light = light();
+        let mut clock = FakeClock::default();
+        light.process(TouchEvent::Approach, clock.now_us);
+        light.process(TouchEvent::Leave, clock.advance_ms(1_000));
+        light.tick(clock.advance_ms(GLOW_LINGER_MS as u64));
+        light.tick(clock.advance_ms(STEP_FADE_MS as u64 / 2));
+        assert!(light.current_level() < GLOW_LEVEL);
+        light.process(TouchEvent::Approach, clock.advance_ms(10));
+        light.tick(clock.advance_ms(GLOW_LINGER_MS as u64 * 2));
+        assert_eq!(light.current_level(), GLOW_LEVEL);
+    }
+
+    #[test]
+    fn test_approach_ignored_when_on() {
+        let mut light = light();
+        light.set_remembered_level(100);
+        light.on();
+        light.process(TouchEvent::Approach, 0);
+        light.process(TouchEvent::Leave, 0);
+        light.tick(GLOW_LINGER_MS as u64 * 2000);
+        assert_eq!(light.current_level(), 100);
+        assert!(!light.is_glowing());
+    }
+
+    #[test]
+    fn test_tap_while_glowing_turns_on() {
+        let mut light = light();
+        let mut clock = FakeClock::default();
+        light.set_remembered_level(150);
+        light.process(TouchEvent::Approach, clock.now_us);
+        light.tick(clock.advance_ms(STEP_FADE_MS as u64));
+        light.process(TouchEvent::Press, clock.advance_ms(100));
+        assert!(light.is_glowing());
+        light.process(TouchEvent::SingleTap, clock.advance_ms(100));
+        assert!(!light.is_glowing());
+        assert_eq!(light.state(), LightState::Rising);
+        light.process(TouchEvent::Leave, clock.advance_ms(100));
+        light.tick(clock.advance_ms(DEFAULT_FADE_MS as u64 + GLOW_LINGER_MS as u64));
+        assert_eq!(light.current_level(), 150);
+    }
+
+    #[test]
+    fn test_long_press_while_glowing_toggles_on() {
+        let mut light = light();
+        light.process(TouchEvent::Approach, 0);
+        light.process(TouchEvent::LongPressStart, GLOW_US);
+        assert_eq!(light.current_level(), 0xff);
+        assert_eq!(light.state(), LightState::On);
+    }
+
+    // --- Slider ---
+
+    #[test]
+    fn test_slider_position_sets_level() {
+        let mut light = light();
+        light.process_slider(SliderEvent::Position(100), 0);
+        assert_eq!(light.spi.bytes, frame(100));
+        assert_eq!(light.state(), LightState::Steady);
+        light.process_slider(SliderEvent::Position(0), 0);
+        assert_eq!(light.current_level(), MIN_DIM_LEVEL);
+    }
+
+    #[test]
+    fn test_slider_position_cancels_fade() {
+        let mut light = light();
+        light.process(TouchEvent::SingleTap, 0);
+        light.process_slider(SliderEvent::Position(60), 1_000);
+        light.tick(DEFAULT_FADE_MS as u64 * 1000);
+        assert_eq!(light.current_level(), 60);
+    }
+
+    #[test]
+    fn test_slider_release_remembers_level() {
+        let mut light = light();
+        light.process_slider(SliderEvent::Position(70), 0);
+        assert_eq!(light.remembered_level(), 0xff);
+        light.process_slider(SliderEvent::Release, 0);
+        assert_eq!(light.remembered_level(), 70);
+    }
+
+    #[test]
+    fn test_slider_swipes() {
+        let mut light = light();
+        light.process_slider(SliderEvent::Swipe(Direction::Forward), 0);
+        light.tick(DEFAULT_FADE_MS as u64 * 1000);
+        assert_eq!(light.state(), LightState::On);
+        light.process_slider(SliderEvent::Swipe(Direction::Backward), 0);
+        assert_eq!(light.state(), LightState::Falling);
+        light.tick(DEFAULT_FADE_MS as u64 * 1000);
+        assert_eq!(light.state(), LightState::Off);
+    }
+
+    // --- Clap actions ---
+
+    #[test]
+    fn test_clap_toggle() {
+        let mut light = light();
+        light.process_clap(ClapAction::Toggle, 0);
+        assert_eq!(light.state(), LightState::On);
+        assert_eq!(light.spi.bytes, frame(0xff));
+        light.process_clap(ClapAction::Toggle, 0);
+        assert_eq!(light.state(), LightState::Off);
+    }
+
+    #[test]
+    fn test_clap_toggle_mid_fade_switches_off() {
+        let mut light = light();
+        light.process(TouchEvent::SingleTap, 0);
+        light.tick(HALF_FADE_MS * 1000);
+        light.process_clap(ClapAction::Toggle, HALF_FADE_MS * 1000);
+        assert_eq!(light.current_level(), 0);
+        assert!(!light.is_fading());
+    }
+
+    #[test]
+    fn test_clap_full_and_night() {
+        let mut light = light();
+        light.process_clap(ClapAction::Night, 0);
+        light.tick(DEFAULT_FADE_MS as u64 * 1000);
+        assert!(light.is_night());
+        assert_eq!(light.current_level(), NIGHT_LEVEL);
+        light.process_clap(ClapAction::Full, DEFAULT_FADE_MS as u64 * 1000);
+        light.tick(DEFAULT_FADE_MS as u64 * 2000);
+        assert!(!light.is_night());
+        assert_eq!(light.state(), LightState::On);
+    }
+
+    #[test]
+    fn test_clap_dim_steps_down() {
+        let mut light = light();
+        light.on();
+        light.process_clap(ClapAction::Dim, 0);
+        light.process_clap(ClapAction::Dim, 1_000);
+        light.tick(STEP_FADE_MS as u64 * 1000 + 1_000);
+        assert_eq!(light.current_level(), 0xff - 2 * STEP_LEVEL);
+    }
+
+    #[test]
+    fn test_clap_scene() {
+        let mut light = light();
+        let scene = Scene { colour: Colour::Temperature(2700), level: 96 };
+        light.process_clap(ClapAction::Scene(scene), 0);
+        light.tick(DEFAULT_FADE_MS as u64 * 1000);
+        assert_eq!(light.colour(), scene.colour);
+        assert_eq!(light.current_level(), 96);
+    }
+
+    #[test]
+    fn test_clap_while_glowing_acts_on_off_light() {
+        let mut light = light();
+        light.process(TouchEvent::Approach, 0);
+        light.process_clap(ClapAction::Full, GLOW_US);
+        assert!(!light.is_glowing());
+        light.tick(GLOW_US + DEFAULT_FADE_MS as u64 * 1000);
+        assert_eq!(light.state(), LightState::On);
+    }
+
+    #[test]
+    fn test_clap_toggle_while_glowing_turns_on() {
+        let mut light = light();
+        light.process(TouchEvent::Approach, 0);
+        light.tick(GLOW_US);
+        assert!(light.current_level() > 0);
+        light.process_clap(ClapAction::Toggle, GLOW_US);
+        assert!(!light.is_glowing());
+        assert_eq!(light.state(), LightState::On);
+        assert_eq!(light.current_level(), 0xff);
+    }
+
+    // --- Sensor faults ---
+
+    /// Expected APA102 transfer for a single LED showing red at level `v`.
+    fn red_frame(v: u8) -> Vec<u8> {
+        let (global, pwm) = brightness(v);
+        vec![0, 0, 0, 0, 0xE0 | global, 0, 0, pwm, 0xFF, 0xFF, 0xFF, 0xFF]
+    }
+
+    fn is_lit(light: &Light<MockSpi>) -> bool {
+        light.spi.bytes.ends_with(&red_frame(FAULT_LEVEL))
+    }
+
+    /// Length of one repeat of the pattern for `fault`.
+    fn period_ms(fault: SensorFault) -> u64 {
+        (blink_count(fault) * 2 * FAULT_BLINK_MS + FAULT_PAUSE_MS) as u64
+    }
+
+    #[test]
+    fn test_fault_blinks_its_code() {
+        let mut light = light();
+        light.process(TouchEvent::Fault(SensorFault::Shorted), 0);
+        let mut lit = Vec::new();
+        for ms in (0..period_ms(SensorFault::Shorted) + 1).step_by(FAULT_BLINK_MS as usize) {
+            light.tick(ms * 1000);
+            lit.push(is_lit(&light));
+        }
+        // Two blinks, a pause, then the pattern again
+        assert_eq!(lit, [true, false, true, false, false, false, false, false, false, false, true]);
+    }
+
+    #[test]
+    fn test_each_fault_has_its_own_count() {
+        let faults = [SensorFault::Open, SensorFault::Shorted, SensorFault::Saturated, SensorFault::NoSamples];
+        for fault in faults {
+            let mut light = light();
+            light.process(TouchEvent::Fault(fault), 0);
+            let mut blinks = 0;
+            let mut was_lit = true;
+            for ms in 1..period_ms(fault) {
+                light.tick(ms * 1000);
+                if is_lit(&light) && !was_lit {
+                    blinks += 1;
+                }
+                was_lit = is_lit(&light);
+            }
+            // The first blink starts at 0
+            assert_eq!(blinks + 1, blink_count(fault), "{:?}", fault);
+        }
+    }
+
+    #[test]
+    fn test_blink_ends_and_restores_light() {
+        let mut light = light();
+        light.on();
+        light.process(TouchEvent::Fault(SensorFault::Open), 0);
+        assert!(light.is_blinking());
+        let end_ms = period_ms(SensorFault::Open) * FAULT_REPEATS as u64;
+        light.tick((end_ms - 1) * 1000);
+        assert!(light.is_blinking());
+        light.tick(end_ms * 1000);
+        assert!(!light.is_blinking());
+        assert!(light.spi.bytes.ends_with(&frame(0xff)));
+        assert_eq!(light.fault(), Some(SensorFault::Open));
+    }
+
+    #[test]
+    fn test_level_changes_are_shown_after_blink() {
+        let mut light = light();
+        light.process(TouchEvent::Fault(SensorFault::Open), 0);
+        light.on();
+        assert!(is_lit(&light));
+        light.tick(period_ms(SensorFault::Open) * FAULT_REPEATS as u64 * 1000);
+        assert!(light.spi.bytes.ends_with(&frame(0xff)));
+    }
+
+    #[test]
+    fn test_fault_stops_hold_ramp() {
+        let mut light = dimmer();
+        let mut clock = FakeClock::default();
+        light.process(TouchEvent::LongPressStart, clock.now_us);
+        light.process(TouchEvent::Fault(SensorFault::Shorted), clock.advance_ms(1_000));
+        let level = light.current_level();
+        assert!(level > MIN_DIM_LEVEL);
+        light.tick(clock.advance_ms(DEFAULT_HOLD_RAMP_MS as u64));
+        assert_eq!(light.current_level(), level);
+        assert_eq!(light.state(), LightState::Steady);
+    }
+
+    #[test]
+    fn test_fault_fades_glow_out() {
+        let mut light = light();
+        light.process(TouchEvent::Approach, 0);
+        light.process(TouchEvent::Fault(SensorFault::Open), GLOW_US);
+        light.tick(GLOW_US * 2 + 1);
+        assert_eq!(light.current_level(), 0);
+        assert!(!light.is_glowing());
+        assert_eq!(light.state(), LightState::Off);
+    }
+
+    #[test]
+    fn test_recovered_stops_blinking() {
+        let mut light = light();
+        light.process(TouchEvent::Fault(SensorFault::NoSamples), 0);
+        light.process(TouchEvent::Recovered, 1_000);
+        assert!(!light.is_blinking());
+        assert_eq!(light.fault(), None);
+        assert!(light.spi.bytes.ends_with(&frame(0)));
+    }
+
+    #[test]
+    fn test_fault_on_any_pad_blinks() {
+        let mut light = light();
+        light.process_pad(0, PadAction::Brighter, TouchEvent::Fault(SensorFault::Saturated), 0);
+        assert!(light.is_blinking());
+        assert_eq!(light.fault(), Some(SensorFault::Saturated));
+        light.process_pad(0, PadAction::Slider, TouchEvent::Recovered, 0);
+        assert!(!light.is_blinking());
+    }
+
+    #[test]
+    fn test_blink_waits_for_every_pad_to_recover() {
+        let mut light = light();
+        light.process_pad(1, PadAction::Brighter, TouchEvent::Fault(SensorFault::Open), 0);
+        light.process_pad(2, PadAction::Dimmer, TouchEvent::Fault(SensorFault::Shorted), 0);
+        light.process_pad(1, PadAction::Brighter, TouchEvent::Recovered, 1_000);
+        assert!(light.is_blinking());
+        assert_eq!(light.fault(), Some(SensorFault::Shorted));
+        light.process_pad(2, PadAction::Dimmer, TouchEvent::Recovered, 2_000);
+        assert!(!light.is_blinking());
+        assert_eq!(light.fault(), None);
+    }
+
+    #[test]
+    fn test_recovery_on_a_healthy_pad_keeps_blinking() {
+        let mut light = light();
+        light.process(TouchEvent::Fault(SensorFault::Saturated), 0);
+        light.process_pad(3, PadAction::Scene(READING), TouchEvent::Recovered, 1_000);
+        assert!(light.is_blinking());
+        assert_eq!(light.fault(), Some(SensorFault::Saturated));
+    }
+
+    // --- Strips ---
+
+    #[test]
+    fn test_end_frame_len_covers_half_a_clock_per_pixel() {
+        assert_eq!(end_frame_len(1), 4);
+        assert_eq!(end_frame_len(30), 4);
+        assert_eq!(end_frame_len(64), 4);
+        assert_eq!(end_frame_len(65), 5);
+        assert_eq!(end_frame_len(144), 9);
+        for n in 1..=256 {
+            assert!(end_frame_len(n) * 8 >= n / 2, "pixels: {}", n);
+        }
+    }
+
+    fn assert_strip_frame_len<const N: usize>() {
+        let mut light = strip::<N>();
+        light.on();
+        let bytes = &light.spi.bytes;
+        assert_eq!(bytes.len(), 4 + 4 * N + end_frame_len(N), "pixels: {}", N);
+        assert_eq!(bytes[..4], [0, 0, 0, 0]);
+        for led in bytes[4..4 + 4 * N].chunks(4) {
+            assert_eq!(led, [0xFF, 0xFF, 0xFF, 0xFF]);
+        }
+        assert!(bytes[4 + 4 * N..].iter().all(|&b| b == 0xFF));
+    }
+
+    #[test]
+    fn test_strip_frame_length() {
+        assert_strip_frame_len::<1>();
+        assert_strip_frame_len::<2>();
+        assert_strip_frame_len::<30>();
+        assert_strip_frame_len::<60>();
+        assert_strip_frame_len::<64>();
+        assert_strip_frame_len::<65>();
+        assert_strip_frame_len::<144>();
+    }
+
+    #[test]
+    fn test_set_pixel_writes_per_pixel_colour() {
+        let mut light = strip::<3>();
+        light.set_pixel(0, RGB8::new(255, 0, 0));
+        light.set_pixel(1, RGB8::new(0, 255, 0));
+        light.set_pixel(2, RGB8::new(0, 0, 255));
+        light.on();
+        assert_eq!(
+            light.spi.bytes[4..16],
+            [0xFF, 0, 0, 0xFF, 0xFF, 0, 0xFF, 0, 0xFF, 0xFF, 0, 0]
+        );
+    }
+
+    #[test]
+    fn test_set_pixel_out_of_range_is_ignored() {
+        let mut light = strip::<2>();
+        light.set_pixel(2, RGB8::new(1, 2, 3));
+        assert_eq!(light.pixel(2), None);
+        assert_eq!(light.pixel(1), Some(WHITE));
+    }
+
+    // --- Brightness mapping ---
+
+    fn output(level: u8) -> u32 {
+        let (global, pwm) = brightness(level);
+        global as u32 * pwm as u32
+    }
+
+    #[test]
+    fn test_brightness_endpoints() {
+        assert_eq!(brightness(0), (0, 0));
+        assert_eq!(brightness(255), (31, 255));
+    }
+
+    #[test]
+    fn test_brightness_is_monotonic_non_decreasing() {
+        for level in 1..=255u8 {
+            assert!(output(level) >= output(level - 1), "level: {}", level);
+        }
+    }
+
+    #[test]
+    fn test_brightness_global_field_in_range() {
+        for level in 0..=255u8 {
+            assert!(brightness(level).0 <= 0x1F, "level: {}", level);
+        }
+    }
+
+    #[test]
+    fn test_brightness_low_levels_are_lit() {
+        // The 8-bit-only table collapsed levels 1..=15 to zero
+        for level in 1..=15u8 {
+            assert!(output(level) > 0, "level: {}", level);
+        }
+        assert!(output(15) < output(16));
+    }
+
+    #[test]
+    fn test_brightness_tracks_squared_target() {
+        for level in 0..=255u32 {
+            let target = level * level * MAX_OUTPUT / (255 * 255);
+            let out = output(level as u8);
+            assert!(out >= target, "level: {}", level);
+            // Error is bounded by one PWM step at the chosen global value
+            assert!(out <= target + 32, "level: {}", level);
+        }
+    }
+
+    // --- Colour ---
+
+    fn led_rgb(bytes: &[u8]) -> (u32, u32, u32) {
+        (bytes[7] as u32, bytes[6] as u32, bytes[5] as u32)
+    }
+
+    #[test]
+    fn test_set_colour_rewrites_strip() {
+        let mut light = strip::<2>();
+        light.on();
+        light.spi.bytes.clear();
+        light.set_colour(Colour::Temperature(2000));
+        assert_eq!(light.colour(), Colour::Temperature(2000));
+        for led in light.spi.bytes[4..12].chunks(4) {
+            assert_eq!(led, [0xFF, 14, 137, 255]);
+        }
+    }
+
+    #[test]
+    fn test_fade_preserves_hue() {
+        let mut light = light();
+        let mut clock = FakeClock::default();
+        light.set_colour(Colour::Temperature(2000));
+        light.process(TouchEvent::SingleTap, clock.now_us);
+        // r:g:b stays 255:137:14 at every step, to within PWM rounding
+        while light.state() == LightState::Rising {
+            light.spi.bytes.clear();
+            light.tick(clock.advance_ms(5));
+            if light.spi.bytes.is_empty() {
+                continue;
+            }
+            let (r, g, b) = led_rgb(&light.spi.bytes);
+            assert!(r > 0);
+            assert!(g.abs_diff(r * 137 / 255) <= 1, "level: {}, r: {}, g: {}", light.current_level(), r, g);
+            assert!(b.abs_diff(r * 14 / 255) <= 1, "level: {}, r: {}, b: {}", light.current_level(), r, b);
+        }
+    }
+
+    #[test]
+    fn test_amber_stays_amber_when_dim() {
+        let mut light = light();
+        light.set_colour(Colour::Rgb(RGB8::new(255, 100, 0)));
+        light.process(TouchEvent::SingleTap, 0);
+        light.spi.bytes.clear();
+        // Level 15 of a 3 s fade
+        light.tick(177_000);
+        assert_eq!(light.current_level(), 15);
+        let (r, g, b) = led_rgb(&light.spi.bytes);
+        assert!(r > g && g > 0 && b == 0, "r: {}, g: {}, b: {}", r, g, b);
+    }
+
+    #[test]
+    fn test_set_colour_replaces_pixel_colours() {
+        let mut light = strip::<3>();
+        light.set_pixel(1, RGB8::new(0, 255, 0));
+        light.set_colour(Colour::Rgb(RGB8::new(255, 0, 0)));
+        for index in 0..3 {
+            assert_eq!(light.pixel(index), Some(RGB8::new(255, 0, 0)));
+        }
+    }
+
+    #[test]
+    fn test_night_mode_keeps_pixel_colours() {
+        let mut light = strip::<2>();
+        light.set_pixel(0, RGB8::new(255, 0, 0));
+        light.set_pixel(1, RGB8::new(0, 0, 255));
+        light.on();
+        light.night_mode(0);
+        assert_eq!(light.pixel(0), Some(RGB8::new(255, 0, 0)));
+        let RGB8 { r, g, b } = kelvin_to_rgb(NIGHT_KELVIN);
+        let (_, pwm) = brightness(0xff);
+        let bytes = &light.spi.bytes;
+        for led in bytes[bytes.len() - 12..bytes.len() - 4].chunks(4) {
+            assert_eq!(led[1..], [scale(b, pwm), scale(g, pwm), scale(r, pwm)]);
+        }
+        // Full brightness leaves night mode with the pixels as they were
+        light.process(TouchEvent::DoubleTap, 1_000);
+        light.tick(1_000 + DEFAULT_FADE_MS as u64 * 1000);
+        let bytes = &light.spi.bytes;
+        assert_eq!(bytes[bytes.len() - 12..bytes.len() - 4], [0xFF, 0, 0, 0xFF, 0xFF, 0xFF, 0, 0]);
+    }
+
+    #[test]
+    fn test_pixel_colour_is_scaled_by_level() {
+        let mut light = strip::<2>();
+        light.fill(RGB8::new(255, 128, 0));
+        light.process(TouchEvent::SingleTap, 0);
+        light.tick(177_000);
+        let (global, pwm) = brightness(15);
+        for led in light.spi.bytes[4..12].chunks(4) {
+            assert_eq!(led, [0xE0 | global, 0, scale(128, pwm), pwm]);
+        }
     }
 }
@@ -3,45 +3,202 @@
 
 use defmt::*;
 use defmt_rtt as _;
+use embedded_hal::blocking::spi::Write;
 use embedded_hal::spi::MODE_0;
 use fugit::RateExtU32;
-use hal::gpio::{FunctionPio0, FunctionPio1, Pin, PullUp, PullNone, FunctionSpi};
+#[cfg(feature = "adc-mic")]
+use hal::dma::DMAExt;
+use hal::gpio::{FunctionPio0, PullUp, FunctionSpi};
+#[cfg(not(feature = "adc-mic"))]
+use hal::gpio::{FunctionPio1, Pin, PullNone};
 use hal::{pac, Clock};
-use hal::pio::PIOExt;
+#[cfg(not(feature = "adc-mic"))]
+use hal::pio::Buffers;
+use hal::pio::{InstalledProgram, PIOBuilder, PIOExt, Rx, StateMachineIndex, Tx, UninitStateMachine};
 use hal::Sio;
 use hal::spi::Spi;
 use panic_halt as _;
 use rp2040_hal as hal;
-use touch_switch::channel::Channel;
-use crate::light::Light;
+use touch_switch::autorange::{AutoRange, DEFAULT_TARGET_CYCLES};
+use touch_switch::channel::{Channel, ChannelConfig, TouchEvent, LEVEL_ONE};
+#[cfg(not(feature = "adc-mic"))]
+use touch_switch::clap::EdgeClock;
+#[cfg(feature = "adc-mic")]
+use touch_switch::clap::EdgeSource;
+use touch_switch::clap::{ClapDetector, ClapEvent, ClapPattern, DOUBLE, LONG_SHORT_SHORT, TRIPLE};
+use touch_switch::filter::FilterStage;
+use touch_switch::light::{ClapAction, Light, PadAction, TouchMode, MAX_PADS};
+use touch_switch::settings::{SaveDelay, Settings};
+use touch_switch::slider::{Layout, Slider};
 
-pub mod light;
+mod flash;
+#[cfg(feature = "adc-mic")]
+mod mic;
 
-const CLAP_DEBOUNCE_US: u64 = 50_000;
-const CLAP_WINDOW_US: u64 = 1_000_000;
-const CLAP_COOLDOWN_US: u64 = 1_000_000;
+/// Number of APA102 LEDs chained on SPI1.
+const STRIP_LEN: usize = 1;
+/// Long touch behaviour: instant on/off, or press-and-hold dimming.
+const TOUCH_MODE: TouchMode = TouchMode::Toggle;
+/// Touch pads on GPIO16-19, one PIO0 state machine each, and what each one
+/// does. `None` leaves that pad's state machine idle. For example, a four
+/// button panel:
+///
+/// ```ignore
+/// [Some(PadAction::Main), Some(PadAction::Brighter), Some(PadAction::Dimmer),
+///  Some(PadAction::Scene(Scene { colour: Colour::Temperature(2700), level: 96 }))]
+/// ```
+const TOUCH_PADS: [Option<PadAction>; MAX_PADS] = [Some(PadAction::Main), None, None, None];
+/// Arrangement of the pads mapped to `PadAction::Slider`, in pad order. Two
+/// or more are needed for a slider or wheel.
+const SLIDER_LAYOUT: Layout = Layout::Slider;
+/// Level (in `LEVEL_ONE` units) an approaching hand must bring a pad below to
+/// wake the light to a dim glow, e.g. `Some(LEVEL_ONE * 4 / 5)`. `None`
+/// turns proximity detection off.
+const PROXIMITY_THRESHOLD: Option<u32> = None;
+/// Milliseconds to wait for a second or third tap. Single taps are reported
+/// this much later.
+const MULTI_TAP_WINDOW_MS: u32 = 300;
+/// Clap patterns and what each does. A pattern that a longer one starts with
+/// (the double clap here) is only acted on once the clap window has passed
+/// without another clap.
+const CLAP_PATTERNS: [(ClapPattern, ClapAction); 3] = [
+    (DOUBLE, ClapAction::Toggle),
+    (TRIPLE, ClapAction::Full),
+    (LONG_SHORT_SHORT, ClapAction::Night),
+];
+/// Longest a clap edge can take to reach the detector after it happened:
+/// comparator edges wait in the PIO FIFO while the loop is busy, at worst
+/// through a flash erase.
+#[cfg(not(feature = "adc-mic"))]
+const CLAP_LATENCY_US: u64 = 100_000;
+#[cfg(feature = "adc-mic")]
+const CLAP_LATENCY_US: u64 = mic::LATENCY_US;
+/// A pad that has sent no sample for this long is reported as faulty. The
+/// longest auto-ranged timeout is ~13 ms per measurement.
+const NO_SAMPLE_US: u64 = 100_000;
 
-enum ClapState {
-    Idle,
-    InBurst { t_last: u64, t_start: u64 },
-    WaitingSecond { t_start: u64 },
-    Cooldown { t_start: u64, t_end: u64 },
+/// One touch pad: its state machine FIFOs, timeout, classifier and action.
+struct TouchPad<SM: StateMachineIndex> {
+    rx: Rx<(pac::PIO0, SM)>,
+    tx: Tx<(pac::PIO0, SM)>,
+    range: AutoRange,
+    /// When the last sample arrived; starts at the first poll.
+    last_sample_us: Option<u64>,
+    /// Tuning at `DEFAULT_SAMPLE_RATE`; `channel` runs it rescaled to the
+    /// ranged timeout.
+    config: ChannelConfig,
+    channel: Channel,
+    /// Index into `TOUCH_PADS`.
+    pad: usize,
+    action: Option<PadAction>,
 }
 
-impl ClapState {
-    fn edge(self, now: u64) -> Self {
-        match self {
-            Self::Idle => Self::InBurst { t_last: now, t_start: now },
-            Self::InBurst { t_start, .. } => Self::InBurst { t_last: now, t_start },
-            Self::WaitingSecond { t_start } => {
-                if now.saturating_sub(t_start) < CLAP_WINDOW_US {
-                    Self::Cooldown { t_start: now, t_end: now + CLAP_COOLDOWN_US }
-                } else {
-                    Self::InBurst { t_last: now, t_start: now }
+impl<SM: StateMachineIndex> TouchPad<SM> {
+    fn new(
+        program: InstalledProgram<pac::PIO0>,
+        sm: UninitStateMachine<(pac::PIO0, SM)>,
+        pin: u8,
+        config: ChannelConfig,
+        pad: usize,
+    ) -> Self {
+        let action = TOUCH_PADS[pad];
+        let (sm, rx, mut tx) = PIOBuilder::from_installed_program(program)
+            .set_pins(pin, 1)
+            .jmp_pin(pin)
+            .build(sm);
+        sm.start();
+        // Unused pads are never fed a timeout, so they wait at `pull block`
+        let range = AutoRange::default();
+        if action.is_some() {
+            tx.write(range.timeout());  // Initial Y for first measurement
+        }
+        TouchPad { rx, tx, range, last_sample_us: None, config, channel: Channel::new(config), pad, action }
+    }
+
+    /// Handle a new sample, if there is one. Returns whether there was.
+    fn poll<S: Write<u8>>(&mut self, light: &mut Light<S, STRIP_LEN>, timer: &pac::TIMER) -> bool {
+        let Some(action) = self.action else {
+            return false;
+        };
+        let now = micros(timer);
+        let Some(val) = self.rx.read() else {
+            let last = *self.last_sample_us.get_or_insert(now);
+            if now.saturating_sub(last) > NO_SAMPLE_US {
+                for event in self.channel.stalled() {
+                    self.report(action, event, light, now);
                 }
             }
-            Self::Cooldown { .. } => self,
+            return false;
+        };
+        self.last_sample_us = Some(now);
+        let ranging = !self.range.is_locked();
+        self.range.update(val);
+        self.tx.write(self.range.timeout());  // Feed Y for next measurement
+        if ranging {
+            // Counts taken while ranging use a different timeout; the
+            // channel warms up once it is locked
+            if self.range.is_locked() {
+                let rate = self.range.sample_rate();
+                info!("Touch timeout ranged to {} ({} samples/s)", self.range.timeout(), rate);
+                let config = self.config.at_sample_rate(rate).unwrap_or_else(|err| {
+                    warn!("Touch config invalid at {} samples/s: {}", rate, Debug2Format(&err));
+                    self.config
+                });
+                self.channel = Channel::new(config);
+            }
+            return true;
+        }
+        for event in self.channel.events(val) {
+            self.report(action, event, light, now);
+        }
+        true
+    }
+
+    /// Pass an event on to the light, logging faults and re-ranging a
+    /// recovered pad.
+    fn report<S: Write<u8>>(
+        &mut self,
+        action: PadAction,
+        event: TouchEvent,
+        light: &mut Light<S, STRIP_LEN>,
+        now: u64,
+    ) {
+        match event {
+            TouchEvent::Fault(fault) => warn!("Touch pad fault: {}", Debug2Format(&fault)),
+            TouchEvent::Recovered => {
+                info!("Touch pad recovered");
+                // The pad may have changed, so size its timeout again
+                self.range = AutoRange::default();
+            }
+            _ => (),
         }
+        light.process_pad(self.pad, action, event, now);
+    }
+
+    /// Samples per second at the pad's current timeout.
+    fn sample_rate(&self) -> u32 {
+        self.range.sample_rate()
+    }
+
+    /// Level to feed a slider, if this pad is part of one.
+    fn slider_level(&self) -> Option<u32> {
+        (self.action == Some(PadAction::Slider)).then(|| self.channel.level())
+    }
+}
+
+fn on_clap<S: Write<u8>>(event: ClapEvent, light: &mut Light<S, STRIP_LEN>, now: u64) {
+    match event {
+        ClapEvent::Clap => debug!("Waiting for next clap..."),
+        ClapEvent::Unrecognised => debug!("Clap pattern not recognised, resetting..."),
+        ClapEvent::Pattern(pattern) => {
+            debug!("Clap pattern of {} claps", pattern.claps());
+            if let Some(&(_, action)) = CLAP_PATTERNS.iter().find(|&&(known, _)| known == pattern) {
+                light.process_clap(action, now);
+            }
+        }
+        ClapEvent::CooldownOver => debug!("Clap cooldown expired, resetting..."),
+        ClapEvent::TooNoisy => warn!("Too noisy for claps, ignoring the sound sensor"),
+        ClapEvent::Quiet => info!("Quiet again, listening for claps"),
     }
 }
 
@@ -97,92 +254,123 @@ fn main() -> ! {
     let spi = Spi::<_, _, _, 8>::new(pac.SPI1, spi_pin_layout)
         .init(&mut pac.RESETS, clocks.peripheral_clock.freq(), 2_500_000u32.Hz(), MODE_0);
 
-    let mut light = Light::new(spi);
+    let mut light: Light<_, STRIP_LEN> = Light::new(spi);
+    light.set_mode(TOUCH_MODE);
+    if let Some(saved) = flash::load() {
+        light.set_remembered_level(saved.level);
+    }
+    let mut level_save = SaveDelay::new(light.remembered_level());
 
-    let touch_pin: Pin<_, FunctionPio0, _> = pins.gpio16.into_function().into_pull_type::<PullUp>();
-    let touch_pin_id = touch_pin.id().num;
-    let sound_pin: Pin<_, FunctionPio1, _> = pins.gpio21.into_function().into_pull_type::<PullNone>();
-    let sound_pin_id = sound_pin.id().num;
+    let touch_pins: [u8; 4] = [
+        pins.gpio16.into_function::<FunctionPio0>().into_pull_type::<PullUp>().id().num,
+        pins.gpio17.into_function::<FunctionPio0>().into_pull_type::<PullUp>().id().num,
+        pins.gpio18.into_function::<FunctionPio0>().into_pull_type::<PullUp>().id().num,
+        pins.gpio19.into_function::<FunctionPio0>().into_pull_type::<PullUp>().id().num,
+    ];
 
     // Initialize and start PIO
-    let (mut pio0, touch_sm, _, _, _) = pac.PIO0.split(&mut pac.RESETS);
-    let (mut pio1, clap_sm, _, _, _) = pac.PIO1.split(&mut pac.RESETS);
+    let (mut pio0, sm0, sm1, sm2, sm3) = pac.PIO0.split(&mut pac.RESETS);
     let installed1 = pio0.install(&pio::pio_file!("./src/touch.pio").program).unwrap();
-    let installed2 = pio1.install(&pio::pio_file!("./src/clap.pio").program).unwrap();
-    let (touch_sm, mut touch_rx, mut tx0) = rp2040_hal::pio::PIOBuilder::from_installed_program(installed1)
-        .set_pins(touch_pin_id, 1)
-        .jmp_pin(touch_pin_id)
-        .build(touch_sm);
-    touch_sm.start();
-    let (clap_sm, mut clap_rx, _tx0) = rp2040_hal::pio::PIOBuilder::from_installed_program(installed2)
-        .in_pin_base(sound_pin_id)
-        .jmp_pin(sound_pin_id)
-        .build(clap_sm);
-    clap_sm.start();
+    #[cfg(not(feature = "adc-mic"))]
+    let (mut clap_rx, edge_clock) = {
+        let sound_pin: Pin<_, FunctionPio1, _> = pins.gpio21.into_function().into_pull_type::<PullNone>();
+        let (mut pio1, clap_sm, _, _, _) = pac.PIO1.split(&mut pac.RESETS);
+        let installed2 = pio1.install(&pio::pio_file!("./src/clap.pio").program).unwrap();
+        // The edge counter ticks once a microsecond, like the timer
+        let (int, frac) = EdgeClock::clock_divisor(clocks.system_clock.freq().to_Hz());
+        let (clap_sm, clap_rx, _tx0) = PIOBuilder::from_installed_program(installed2)
+            .jmp_pin(sound_pin.id().num)
+            .clock_divisor_fixed_point(int, frac)
+            .buffers(Buffers::OnlyRx)
+            .build(clap_sm);
+        clap_sm.start();
+        // Counting from now, as the state machine has just started
+        let edge_clock = EdgeClock::new(micros(&pac.TIMER));
+        (clap_rx, edge_clock)
+    };
+    // The ADC and DMA sample the microphone in the background instead
+    #[cfg(feature = "adc-mic")]
+    let mut mic = {
+        let dma = pac.DMA.split(&mut pac.RESETS);
+        let pin = pins.gpio26.into_floating_input();
+        mic::Mic::new(pac.ADC, pin, (dma.ch0, dma.ch1), &mut pac.RESETS, micros(&pac.TIMER))
+    };
     // PIO runs in background, independently from CPU
 
-    let mut channel = Channel::new();
-    let mut clap_state = ClapState::Idle;
-    tx0.write(200_000);  // Initial Y for first measurement
+    let mut touch_config = ChannelConfig::builder()
+        .multi_tap_window_ms(MULTI_TAP_WINDOW_MS)
+        // Drop LED SPI spikes, null 50 Hz hum, then smooth what is left
+        .filter(FilterStage::Median(5))
+        .filter(FilterStage::Notch(6))
+        .filter(FilterStage::Ema(2))
+        // Far more cycles than auto-ranging aims for means the pad is
+        // disconnected; far fewer, that it is swamped
+        .fault_cycles(DEFAULT_TARGET_CYCLES / 16, DEFAULT_TARGET_CYCLES * 4);
+    if let Some(level) = PROXIMITY_THRESHOLD {
+        touch_config = touch_config.proximity_threshold(level);
+    }
+    let touch_config = touch_config.build().unwrap();
+    // All four state machines run the same program
+    let (program0, program1, program2) = unsafe { (installed1.share(), installed1.share(), installed1.share()) };
+    let mut pad0 = TouchPad::new(program0, sm0, touch_pins[0], touch_config, 0);
+    let mut pad1 = TouchPad::new(program1, sm1, touch_pins[1], touch_config, 1);
+    let mut pad2 = TouchPad::new(program2, sm2, touch_pins[2], touch_config, 2);
+    let mut pad3 = TouchPad::new(installed1, sm3, touch_pins[3], touch_config, 3);
+    let slider_pads = TOUCH_PADS.iter().filter(|&&action| action == Some(PadAction::Slider)).count();
+    // The slider moves on with the first of its pads' samples
+    let first_slider_pad = TOUCH_PADS.iter().position(|&action| action == Some(PadAction::Slider));
+    let mut slider = Slider::new(SLIDER_LAYOUT, slider_pads);
+    let mut clap = ClapDetector::new();
+    clap.set_patterns(&CLAP_PATTERNS.map(|(pattern, _)| pattern));
+    clap.set_latency(CLAP_LATENCY_US);
+    #[cfg(feature = "adc-mic")]
+    clap.set_source(EdgeSource::Onsets);
 
     debug!("Looping now...");
 
     loop {
-        match touch_rx.read() {
-            Some(val) => {
-                let next = 200_000;
-                tx0.write(next);  // Feed Y for next measurement
-                light.process(channel.state(val));
+        let sampled = [
+            pad0.poll(&mut light, &pac.TIMER),
+            pad1.poll(&mut light, &pac.TIMER),
+            pad2.poll(&mut light, &pac.TIMER),
+            pad3.poll(&mut light, &pac.TIMER),
+        ];
+        if let Some(pad) = first_slider_pad.filter(|&pad| slider_pads >= 2 && sampled[pad]) {
+            let rate = [pad0.sample_rate(), pad1.sample_rate(), pad2.sample_rate(), pad3.sample_rate()][pad];
+            if rate != slider.sample_rate() {
+                slider.set_sample_rate(rate);
+            }
+            let mut levels = [LEVEL_ONE; 4];
+            let pad_levels = [pad0.slider_level(), pad1.slider_level(), pad2.slider_level(), pad3.slider_level()];
+            for (slot, level) in levels.iter_mut().zip(pad_levels.into_iter().flatten()) {
+                *slot = level;
+            }
+            if let Some(event) = slider.update(&levels[..slider_pads]) {
+                light.process_slider(event, micros(&pac.TIMER));
             }
-            None => ()
         }
 
         let now = micros(&pac.TIMER);
+        light.tick(now);
 
-        // Process all pending clap edges
-        while let Some(_) = clap_rx.read() {
-            clap_state = clap_state.edge(now);
-            if matches!(clap_state, ClapState::Cooldown { .. }) {
-                while let Some(_) = clap_rx.read() {}
-                break;
+        // Persist the remembered level once the light has settled on a new
+        // one and it has stopped changing for a few seconds
+        if !light.is_fading() {
+            if let Some(level) = level_save.update(light.remembered_level(), now) {
+                debug!("Saving level {}", level);
+                flash::save(&Settings { level });
             }
         }
 
-        // Check time-based state transitions
-        clap_state = match clap_state {
-            ClapState::InBurst { t_last, t_start } => {
-                if now.saturating_sub(t_last) > CLAP_DEBOUNCE_US {
-                    debug!("Waiting for second clap...");
-                    ClapState::WaitingSecond { t_start }
-                } else {
-                    ClapState::InBurst { t_last, t_start }
-                }
-            }
-            ClapState::WaitingSecond { t_start } => {
-                if now.saturating_sub(t_start) > CLAP_WINDOW_US {
-                    debug!("Clap window expired, resetting...");
-                    ClapState::Idle
-                } else {
-                    ClapState::WaitingSecond { t_start }
-                }
-            }
-            ClapState::Cooldown { t_start, t_end } => {
-                if now == t_start {
-                    debug!("Clap cooldown started");
-                    if light.current_level() > 0 {
-                        light.off();
-                    } else {
-                        light.on();
-                    }
-                }
-                if now >= t_end {
-                    debug!("Clap cooldown expired, resetting...");
-                    ClapState::Idle
-                } else {
-                    ClapState::Cooldown { t_start, t_end }
-                }
-            }
-            other => other,
-        };
+        // Process all pending clap edges at the time they were captured
+        #[cfg(not(feature = "adc-mic"))]
+        while let Some(count) = clap_rx.read() {
+            clap.edge(edge_clock.timestamp(count, now));
+        }
+        #[cfg(feature = "adc-mic")]
+        mic.poll(now, |t| clap.edge(t));
+        while let Some(event) = clap.tick(now) {
+            on_clap(event, &mut light, now);
+        }
     }
 }
@@ -0,0 +1,116 @@
+//! Analog microphone output on GPIO26, sampled by the ADC into a pair of DMA
+//! buffers and searched for clap onsets. Replaces `clap.pio` and the
+//! comparator output when built with the `adc-mic` feature.
+
+use cortex_m::singleton;
+use defmt::warn;
+use rp2040_hal::adc::{Adc, AdcFifo, AdcPin, DmaReadTarget};
+use rp2040_hal::dma::double_buffer::{Config, Transfer, WriteNext};
+use rp2040_hal::dma::{Channel, CH0, CH1};
+use rp2040_hal::gpio::bank0::Gpio26;
+use rp2040_hal::gpio::{FunctionSioInput, Pin, PullNone};
+use rp2040_hal::pac;
+use touch_switch::onset::{OnsetDetector, DEFAULT_MAX_LENGTH_US, DEFAULT_SAMPLE_RATE};
+
+/// Samples per DMA buffer (16 ms).
+const BLOCK_LEN: usize = 128;
+const SAMPLE_US: u64 = 1_000_000 / DEFAULT_SAMPLE_RATE as u64;
+const BLOCK_US: u64 = BLOCK_LEN as u64 * SAMPLE_US;
+/// Longest from a clap starting to its onset reaching `Mic::poll`'s
+/// callback: the onset is only known once the sound has died away, then
+/// its buffer has to fill and be polled.
+pub const LATENCY_US: u64 = DEFAULT_MAX_LENGTH_US + 2 * BLOCK_US;
+/// Divider from the 48 MHz ADC clock to `DEFAULT_SAMPLE_RATE`.
+const CLOCK_DIVIDER: u16 = (48_000_000 / DEFAULT_SAMPLE_RATE - 1) as u16;
+
+pub type MicPin = Pin<Gpio26, FunctionSioInput, PullNone>;
+type Block = &'static mut [u16; BLOCK_LEN];
+type Dma = Transfer<Channel<CH0>, Channel<CH1>, DmaReadTarget<u16>, Block, WriteNext<Block>>;
+
+pub struct Mic {
+    /// Filling one buffer with the other queued; only `None` while a full
+    /// buffer is being processed.
+    dma: Option<Dma>,
+    fifo: AdcFifo<'static, u16>,
+    _pin: AdcPin<MicPin>,
+    onset: OnsetDetector,
+    /// Time of the first sample in the next buffer to complete. The ADC
+    /// runs off the same crystal as the timer, so counting samples keeps
+    /// this exact however late the buffer is read.
+    block_start_us: u64,
+    /// Whether CH1 rather than CH0 fills the next buffer to complete.
+    filling_ch1: bool,
+}
+
+impl Mic {
+    /// Start sampling `pin` continuously from `now`. Can only be called once.
+    pub fn new(
+        adc: pac::ADC,
+        pin: MicPin,
+        channels: (Channel<CH0>, Channel<CH1>),
+        resets: &mut pac::RESETS,
+        now: u64,
+    ) -> Self {
+        let adc = singleton!(: Adc = Adc::new(adc, resets)).unwrap();
+        let mut pin = AdcPin::new(pin).unwrap();
+        let mut fifo = adc
+            .build_fifo()
+            .clock_divider(CLOCK_DIVIDER, 0)
+            .set_channel(&mut pin)
+            .enable_dma()
+            .start_paused();
+        let first = singleton!(: [u16; BLOCK_LEN] = [0; BLOCK_LEN]).unwrap();
+        let second = singleton!(: [u16; BLOCK_LEN] = [0; BLOCK_LEN]).unwrap();
+        let dma = Config::new(channels, fifo.dma_read_target(), first).start().write_next(second);
+        fifo.resume();
+        Mic {
+            dma: Some(dma),
+            fifo,
+            _pin: pin,
+            onset: OnsetDetector::new(),
+            block_start_us: now,
+            filling_ch1: false,
+        }
+    }
+
+    /// Search the buffer just filled, if there is one, passing the start of
+    /// each clap to `on_onset`. Should be called at least once a buffer: if
+    /// both buffers fill before one is handed back, samples are lost and
+    /// capture restarts from `now`.
+    pub fn poll(&mut self, now: u64, mut on_onset: impl FnMut(u64)) {
+        if !self.dma.as_ref().is_some_and(|dma| dma.is_done()) {
+            return;
+        }
+        let (block, dma) = self.dma.take().unwrap().wait();
+        self.filling_ch1 = !self.filling_ch1;
+        self.search(block, &mut on_onset);
+        // The FIFO only overflows once the DMA has stopped reading it
+        if !self.fifo.is_over() {
+            self.dma = Some(dma.write_next(block));
+            return;
+        }
+        // The other buffer filled before this one was queued, so nothing will
+        // start it. Its contents may be from the buffer before last, so drop
+        // it, queue both buffers again and restart the one that fills first
+        warn!("Microphone overrun: samples lost, restarting capture");
+        let (other, dma) = dma.write_next(block).wait();
+        self.filling_ch1 = !self.filling_ch1;
+        self.dma = Some(dma.write_next(other));
+        self.fifo.clear();
+        self.block_start_us = now;
+        let channel = if self.filling_ch1 { 1 } else { 0 };
+        // Safety: only starts the channel that owns the queued buffer, which
+        // is idle; the transfer in `self.dma` expects it to be running
+        unsafe { (*pac::DMA::ptr()).multi_chan_trigger().write(|w| w.bits(1 << channel)) };
+    }
+
+    fn search(&mut self, block: &[u16; BLOCK_LEN], on_onset: &mut impl FnMut(u64)) {
+        let start = self.block_start_us;
+        self.block_start_us += BLOCK_US;
+        for (i, &sample) in block.iter().enumerate() {
+            if let Some(t) = self.onset.update(sample, start + i as u64 * SAMPLE_US) {
+                on_onset(t);
+            }
+        }
+    }
+}
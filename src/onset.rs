@@ -0,0 +1,353 @@
+//! Clap onsets from the analog microphone output, sampled by the ADC.
+//!
+//! The sound sensor's digital output is a comparator on the same signal, so
+//! it fires on anything loud: a door slam or music trips it as readily as a
+//! clap. `OnsetDetector` looks at the waveform instead. It removes the DC
+//! bias, follows the rectified signal with a fast envelope and a slow
+//! background level, and reports an onset when the envelope jumps well above
+//! the background and falls back within a clap's length. Sustained sound
+//! raises the background with it, and a sound that starts sharply but lasts
+//! longer than a clap is dropped.
+//!
+//! The time constants are in samples and sized for `DEFAULT_SAMPLE_RATE`.
+//! Onsets are reported with the time of the sample that started them, so
+//! they can be fed to `ClapDetector::edge` like comparator edges.
+
+use crate::filter::ema;
+
+/// ADC samples per second the detector is tuned for.
+pub const DEFAULT_SAMPLE_RATE: u32 = 8_000;
+/// Envelope to background ratio that starts an onset.
+pub const DEFAULT_RATIO: u32 = 4;
+/// Smallest envelope, in ADC counts from the bias, that starts an onset.
+pub const DEFAULT_MIN_LEVEL: u32 = 100;
+/// Longest a clap stays above the background.
+pub const DEFAULT_MAX_LENGTH_US: u64 = 40_000;
+
+/// DC bias time constant, as a power of two samples (~0.5 s).
+const DC_SHIFT: u32 = 12;
+/// Envelope attack time constant, as a power of two samples (~1 ms).
+const ATTACK_SHIFT: u32 = 3;
+/// Envelope release time constant (~8 ms), long enough to ride over the
+/// ripple of a rectified low tone rather than end and restart on each cycle.
+const RELEASE_SHIFT: u32 = 6;
+/// Background time constant, as a power of two samples (~0.25 s).
+const BACKGROUND_SHIFT: u32 = 11;
+/// Samples before the bias and background are trusted.
+const WARMUP_SAMPLES: u32 = 1 << BACKGROUND_SHIFT;
+const FRAC_BITS: u32 = 8;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum OnsetState {
+    Quiet,
+    /// Above the background since `t_start`.
+    Transient { t_start: u64 },
+    /// Above the background for longer than a clap.
+    Sustained,
+}
+
+pub struct OnsetDetector {
+    ratio: u32,
+    min_level: u32,
+    max_length_us: u64,
+    /// DC bias, envelope and background, with `FRAC_BITS` fraction bits.
+    dc: u32,
+    envelope: u32,
+    background: u32,
+    warmup: u32,
+    state: OnsetState,
+}
+
+impl Default for OnsetDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OnsetDetector {
+    pub fn new() -> Self {
+        OnsetDetector {
+            ratio: DEFAULT_RATIO,
+            min_level: DEFAULT_MIN_LEVEL,
+            max_length_us: DEFAULT_MAX_LENGTH_US,
+            dc: 0,
+            envelope: 0,
+            background: 0,
+            warmup: WARMUP_SAMPLES,
+            state: OnsetState::Quiet,
+        }
+    }
+
+    /// Set how far above the background (at least 2x) and above the bias an
+    /// onset must rise.
+    pub fn set_threshold(&mut self, ratio: u32, min_level: u32) {
+        self.ratio = ratio.max(2);
+        self.min_level = min_level;
+    }
+
+    /// Set the longest sound that still counts as a clap.
+    pub fn set_max_length(&mut self, max_length_us: u64) {
+        self.max_length_us = max_length_us;
+    }
+
+    /// Envelope in ADC counts from the bias.
+    pub fn envelope(&self) -> u32 {
+        self.envelope >> FRAC_BITS
+    }
+
+    /// Background level in ADC counts from the bias.
+    pub fn background(&self) -> u32 {
+        self.background >> FRAC_BITS
+    }
+
+    /// Feed one ADC sample taken at `t` (microseconds, monotonic). Returns
+    /// the start of a clap once it has died away.
+    pub fn update(&mut self, sample: u16, t: u64) -> Option<u64> {
+        let x = (sample as u32) << FRAC_BITS;
+        if self.warmup == WARMUP_SAMPLES {
+            self.dc = x;
+        }
+        self.dc = ema(self.dc, x, DC_SHIFT);
+        let rectified = x.abs_diff(self.dc);
+        let shift = if rectified > self.envelope { ATTACK_SHIFT } else { RELEASE_SHIFT };
+        self.envelope = ema(self.envelope, rectified, shift);
+        self.background = ema(self.background, rectified, BACKGROUND_SHIFT);
+        if self.warmup > 0 {
+            self.warmup -= 1;
+            return None;
+        }
+
+        let level = self.envelope();
+        let background = self.background();
+        let start = level > (background * self.ratio).max(self.min_level);
+        // Half the starting threshold ends it, so ripple does not split a clap
+        let end = level < (background * self.ratio / 2).max(self.min_level / 2);
+        match self.state {
+            OnsetState::Quiet if start => {
+                self.state = OnsetState::Transient { t_start: t };
+                None
+            }
+            OnsetState::Transient { t_start } if end => {
+                self.state = OnsetState::Quiet;
+                Some(t_start)
+            }
+            OnsetState::Transient { t_start } if t.saturating_sub(t_start) > self.max_length_us => {
+                self.state = OnsetState::Sustained;
+                None
+            }
+            OnsetState::Sustained if end => {
+                self.state = OnsetState::Quiet;
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::f64::consts::PI;
+
+    /// Mid-rail bias of the microphone output.
+    const BIAS: f64 = 2048.0;
+    const SAMPLE_US: u64 = 1_000_000 / DEFAULT_SAMPLE_RATE as u64;
+
+    /// Uniform noise in `-1.0..1.0`.
+    struct Noise(u32);
+
+    impl Noise {
+        fn next(&mut self) -> f64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0 as f64 / u32::MAX as f64 * 2.0 - 1.0
+        }
+    }
+
+    /// A synthetic waveform: the bias plus the sum of the given sounds,
+    /// each a function of time in seconds.
+    struct Waveform {
+        noise: Noise,
+        sounds: Vec<Box<dyn Fn(f64, f64) -> f64>>,
+    }
+
+    impl Waveform {
+        fn new() -> Self {
+            Waveform { noise: Noise(0x1234_5678), sounds: Vec::new() }
+        }
+
+        /// Add a sound built from the time and a fresh noise sample.
+        fn with(mut self, sound: impl Fn(f64, f64) -> f64 + 'static) -> Self {
+            self.sounds.push(Box::new(sound));
+            self
+        }
+
+        /// Background hiss of `amplitude` counts.
+        fn hiss(self, amplitude: f64) -> Self {
+            self.with(move |_, n| amplitude * n)
+        }
+
+        /// A clap at `at` seconds: a noise burst that decays in a few ms.
+        fn clap(self, at: f64, amplitude: f64) -> Self {
+            self.with(move |t, n| if t < at { 0.0 } else { amplitude * n * (-(t - at) / 0.003).exp() })
+        }
+
+        /// A steady tone of `freq` Hz.
+        fn tone(self, freq: f64, amplitude: f64) -> Self {
+            self.with(move |t, _| amplitude * (2.0 * PI * freq * t).sin())
+        }
+
+        /// Music-like sound from `from` seconds on: noise whose loudness
+        /// swells and fades a few times a second.
+        fn music(self, from: f64, amplitude: f64) -> Self {
+            self.with(move |t, n| {
+                if t < from {
+                    0.0
+                } else {
+                    amplitude * n * (0.6 + 0.4 * (2.0 * PI * 3.0 * t).sin())
+                }
+            })
+        }
+
+        /// A door slam at `at` seconds: a low thud that rings for a tenth of
+        /// a second.
+        fn slam(self, at: f64, amplitude: f64) -> Self {
+            self.with(move |t, n| {
+                if t < at {
+                    0.0
+                } else {
+                    let decay = (-(t - at) / 0.08).exp();
+                    amplitude * decay * (0.8 * (2.0 * PI * 70.0 * (t - at)).sin() + 0.2 * n)
+                }
+            })
+        }
+
+        /// Run `seconds` of the waveform through `detector`, returning the
+        /// onsets in milliseconds.
+        fn onsets(mut self, detector: &mut OnsetDetector, seconds: f64) -> Vec<u64> {
+            let samples = (seconds * DEFAULT_SAMPLE_RATE as f64) as u64;
+            let mut onsets = Vec::new();
+            for i in 0..samples {
+                let t = i * SAMPLE_US;
+                let secs = t as f64 / 1e6;
+                let mut value = BIAS;
+                for sound in &self.sounds {
+                    value += sound(secs, self.noise.next());
+                }
+                let sample = value.round().clamp(0.0, 4095.0) as u16;
+                if let Some(onset) = detector.update(sample, t) {
+                    onsets.push(onset / 1000);
+                }
+            }
+            onsets
+        }
+    }
+
+    fn onsets(waveform: Waveform, seconds: f64) -> Vec<u64> {
+        waveform.onsets(&mut OnsetDetector::new(), seconds)
+    }
+
+    // --- Claps ---
+
+    #[test]
+    fn test_clap_in_quiet_room() {
+        let found = onsets(Waveform::new().hiss(5.0).clap(0.5, 1500.0), 1.0);
+        assert_eq!(found, vec![500]);
+    }
+
+    #[test]
+    fn test_double_clap() {
+        let found = onsets(Waveform::new().hiss(5.0).clap(0.5, 1500.0).clap(0.8, 1200.0), 1.5);
+        assert_eq!(found, vec![500, 800]);
+    }
+
+    #[test]
+    fn test_clap_over_steady_noise() {
+        let waveform = Waveform::new().hiss(80.0).tone(50.0, 100.0).clap(0.6, 1800.0);
+        assert_eq!(onsets(waveform, 1.0), vec![600]);
+    }
+
+    #[test]
+    fn test_clap_over_music() {
+        let waveform = Waveform::new().music(0.0, 150.0).clap(1.0, 1900.0);
+        assert_eq!(onsets(waveform, 1.5), vec![1000]);
+    }
+
+    #[test]
+    fn test_quiet_clap_is_ignored() {
+        let found = onsets(Waveform::new().hiss(2.0).clap(0.5, 60.0), 1.0);
+        assert!(found.is_empty(), "{:?}", found);
+    }
+
+    #[test]
+    fn test_raised_threshold_ignores_softer_claps() {
+        let mut detector = OnsetDetector::new();
+        detector.set_threshold(DEFAULT_RATIO, 400);
+        let found = Waveform::new().hiss(5.0).clap(0.5, 400.0).clap(1.0, 1800.0).onsets(&mut detector, 1.5);
+        assert_eq!(found, vec![1000]);
+    }
+
+    // --- Sustained sound ---
+
+    #[test]
+    fn test_silence() {
+        assert!(onsets(Waveform::new().hiss(5.0), 2.0).is_empty());
+    }
+
+    #[test]
+    fn test_steady_hum() {
+        let found = onsets(Waveform::new().hiss(5.0).tone(50.0, 600.0), 2.0);
+        assert!(found.is_empty(), "{:?}", found);
+    }
+
+    #[test]
+    fn test_loud_noise() {
+        let found = onsets(Waveform::new().hiss(1000.0), 2.0);
+        assert!(found.is_empty(), "{:?}", found);
+    }
+
+    #[test]
+    fn test_music_starting_abruptly() {
+        let found = onsets(Waveform::new().hiss(5.0).music(0.5, 800.0), 3.0);
+        assert!(found.is_empty(), "{:?}", found);
+    }
+
+    #[test]
+    fn test_door_slam() {
+        let found = onsets(Waveform::new().hiss(5.0).slam(0.5, 1500.0), 1.5);
+        assert!(found.is_empty(), "{:?}", found);
+    }
+
+    #[test]
+    fn test_longer_max_length_accepts_slam() {
+        let mut detector = OnsetDetector::new();
+        detector.set_max_length(1_000_000);
+        let found = Waveform::new().hiss(5.0).slam(0.5, 1500.0).onsets(&mut detector, 1.5);
+        assert_eq!(found, vec![500]);
+    }
+
+    // --- Warmup ---
+
+    #[test]
+    fn test_bias_settles_without_onsets() {
+        // Output well off mid-rail from the first sample
+        let found = onsets(Waveform::new().hiss(5.0).with(|_, _| 900.0), 1.0);
+        assert!(found.is_empty(), "{:?}", found);
+    }
+
+    #[test]
+    fn test_no_onsets_during_warmup() {
+        let found = onsets(Waveform::new().hiss(5.0).clap(0.1, 1500.0).clap(0.6, 1500.0), 1.0);
+        assert_eq!(found, vec![600]);
+    }
+
+    #[test]
+    fn test_levels_track_signal() {
+        let mut detector = OnsetDetector::new();
+        Waveform::new().tone(100.0, 500.0).onsets(&mut detector, 1.0);
+        // Mean of a rectified sine is 2/pi of its amplitude
+        assert!(detector.background().abs_diff(318) < 30, "{}", detector.background());
+        assert!(detector.envelope() < 500, "{}", detector.envelope());
+    }
+}
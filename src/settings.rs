@@ -0,0 +1,261 @@
+//! Settings records stored in a reserved flash sector.
+//!
+//! Each save programs the next erased record slot of the sector, so the
+//! sector is only erased once every `SECTOR_SIZE / RECORD_SIZE` saves. Flash
+//! is programmed a page at a time, so a save programs the slot's page with
+//! every other byte left erased (0xFF), which leaves the records already
+//! there unchanged. The newest valid record wins when loading.
+
+/// Flash programming granularity.
+pub const PAGE_SIZE: usize = 256;
+/// Flash erase granularity.
+pub const SECTOR_SIZE: usize = 4096;
+/// Bytes per settings record.
+pub const RECORD_SIZE: usize = 8;
+/// Default time a new value must hold before it is saved.
+pub const DEFAULT_SAVE_DELAY_US: u64 = 5_000_000;
+
+const MAGIC: [u8; 4] = *b"TSW1";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Settings {
+    /// Remembered non-zero light level.
+    pub level: u8,
+}
+
+impl Settings {
+    /// Encode as one record; unused bytes stay erased (0xFF).
+    pub fn encode(&self) -> [u8; RECORD_SIZE] {
+        let mut record = [0xFFu8; RECORD_SIZE];
+        record[..4].copy_from_slice(&MAGIC);
+        record[4] = self.level;
+        record[5] = !self.level;
+        record
+    }
+
+    pub fn decode(record: &[u8]) -> Option<Self> {
+        if record.len() < 6 || record[..4] != MAGIC || record[5] != !record[4] || record[4] == 0 {
+            return None;
+        }
+        Some(Settings { level: record[4] })
+    }
+
+    /// The page holding record slot `slot`, as it must be programmed to add
+    /// this record: erased apart from the record itself.
+    pub fn page_image(&self, slot: usize) -> [u8; PAGE_SIZE] {
+        let mut page = [0xFFu8; PAGE_SIZE];
+        let start = slot * RECORD_SIZE % PAGE_SIZE;
+        page[start..start + RECORD_SIZE].copy_from_slice(&self.encode());
+        page
+    }
+}
+
+/// Page of the sector that holds record slot `slot`.
+pub const fn slot_page(slot: usize) -> usize {
+    slot * RECORD_SIZE / PAGE_SIZE
+}
+
+/// Newest valid record in `sector`, if any.
+pub fn load(sector: &[u8]) -> Option<Settings> {
+    sector.chunks(RECORD_SIZE).filter_map(Settings::decode).next_back()
+}
+
+/// Index of the first erased record slot after the last programmed one, or
+/// `None` if the sector is full and must be erased before the next save.
+pub fn next_free_slot(sector: &[u8]) -> Option<usize> {
+    let used = sector
+        .chunks(RECORD_SIZE)
+        .rposition(|record| record.iter().any(|&b| b != 0xFF))
+        .map_or(0, |last| last + 1);
+    (used < SECTOR_SIZE / RECORD_SIZE).then_some(used)
+}
+
+/// Holds back saving a value until it has stopped changing for a while, so
+/// a run of adjustments costs one flash write rather than one each.
+pub struct SaveDelay<T> {
+    saved: T,
+    /// The unsaved value and when it was first seen.
+    pending: Option<(T, u64)>,
+    delay_us: u64,
+}
+
+impl<T: Copy + PartialEq> SaveDelay<T> {
+    /// Start from `saved`, the value already in flash.
+    pub fn new(saved: T) -> Self {
+        SaveDelay {
+            saved,
+            pending: None,
+            delay_us: DEFAULT_SAVE_DELAY_US,
+        }
+    }
+
+    /// Set how long a new value must hold before it is saved.
+    pub fn set_delay(&mut self, delay_us: u64) {
+        self.delay_us = delay_us;
+    }
+
+    /// Offer the current value at `now` (microseconds, monotonic). Returns it
+    /// once it is due to be saved, and takes it as saved.
+    pub fn update(&mut self, value: T, now: u64) -> Option<T> {
+        if value == self.saved {
+            self.pending = None;
+            return None;
+        }
+        match self.pending {
+            Some((pending, since)) if pending == value => {
+                if now.saturating_sub(since) < self.delay_us {
+                    return None;
+                }
+                self.saved = value;
+                self.pending = None;
+                Some(value)
+            }
+            _ => {
+                self.pending = Some((value, now));
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn erased() -> Vec<u8> {
+        vec![0xFF; SECTOR_SIZE]
+    }
+
+    /// Program `settings` as flash does: bits can only be cleared.
+    fn program(sector: &mut [u8], settings: Settings) {
+        let slot = next_free_slot(sector).unwrap();
+        let page = slot_page(slot);
+        let image = settings.page_image(slot);
+        for (byte, new) in sector[page * PAGE_SIZE..(page + 1) * PAGE_SIZE].iter_mut().zip(image) {
+            *byte &= new;
+        }
+    }
+
+    const SLOTS: usize = SECTOR_SIZE / RECORD_SIZE;
+    const S: u64 = 1_000_000;
+
+    #[test]
+    fn test_encode_decode_roundtrip() {
+        let settings = Settings { level: 42 };
+        assert_eq!(Settings::decode(&settings.encode()), Some(settings));
+    }
+
+    #[test]
+    fn test_decode_rejects_erased_and_corrupt_records() {
+        assert_eq!(Settings::decode(&[0xFF; RECORD_SIZE]), None);
+        let mut page = Settings { level: 42 }.encode();
+        page[5] ^= 1;
+        assert_eq!(Settings::decode(&page), None);
+        let mut page = Settings { level: 42 }.encode();
+        page[0] = b'X';
+        assert_eq!(Settings::decode(&page), None);
+        assert_eq!(Settings::decode(&Settings { level: 0 }.encode()), None);
+    }
+
+    #[test]
+    fn test_load_erased_sector_is_none() {
+        assert_eq!(load(&erased()), None);
+        assert_eq!(next_free_slot(&erased()), Some(0));
+    }
+
+    #[test]
+    fn test_newest_record_wins() {
+        let mut sector = erased();
+        program(&mut sector, Settings { level: 10 });
+        program(&mut sector, Settings { level: 20 });
+        assert_eq!(load(&sector), Some(Settings { level: 20 }));
+        assert_eq!(next_free_slot(&sector), Some(2));
+    }
+
+    #[test]
+    fn test_records_share_a_page() {
+        let mut sector = erased();
+        program(&mut sector, Settings { level: 10 });
+        program(&mut sector, Settings { level: 20 });
+        assert_eq!(Settings::decode(&sector[..RECORD_SIZE]), Some(Settings { level: 10 }));
+        assert_eq!(Settings::decode(&sector[RECORD_SIZE..2 * RECORD_SIZE]), Some(Settings { level: 20 }));
+        assert!(sector[PAGE_SIZE..].iter().all(|&b| b == 0xFF));
+    }
+
+    #[test]
+    fn test_slots_fill_pages_in_order() {
+        assert_eq!(slot_page(0), 0);
+        assert_eq!(slot_page(PAGE_SIZE / RECORD_SIZE - 1), 0);
+        assert_eq!(slot_page(PAGE_SIZE / RECORD_SIZE), 1);
+        assert_eq!(slot_page(SLOTS - 1), SECTOR_SIZE / PAGE_SIZE - 1);
+    }
+
+    #[test]
+    fn test_full_sector_needs_erase() {
+        let mut sector = erased();
+        for i in 0..SLOTS {
+            program(&mut sector, Settings { level: (i % 255 + 1) as u8 });
+        }
+        assert_eq!(next_free_slot(&sector), None);
+        assert_eq!(load(&sector), Some(Settings { level: ((SLOTS - 1) % 255 + 1) as u8 }));
+    }
+
+    #[test]
+    fn test_old_page_records_still_load() {
+        // One record at the start of each page, as earlier firmware saved
+        let mut sector = erased();
+        for (page, level) in [10u8, 20].into_iter().enumerate() {
+            let start = page * PAGE_SIZE;
+            sector[start..start + RECORD_SIZE].copy_from_slice(&Settings { level }.encode());
+        }
+        assert_eq!(load(&sector), Some(Settings { level: 20 }));
+        // New records follow the last old one
+        assert_eq!(next_free_slot(&sector), Some(PAGE_SIZE / RECORD_SIZE + 1));
+    }
+
+    // --- Save delay ---
+
+    #[test]
+    fn test_unchanged_value_is_not_saved() {
+        let mut delay = SaveDelay::new(10u8);
+        assert_eq!(delay.update(10, 0), None);
+        assert_eq!(delay.update(10, 60 * S), None);
+    }
+
+    #[test]
+    fn test_new_value_saved_after_delay() {
+        let mut delay = SaveDelay::new(10u8);
+        assert_eq!(delay.update(20, 0), None);
+        assert_eq!(delay.update(20, DEFAULT_SAVE_DELAY_US - 1), None);
+        assert_eq!(delay.update(20, DEFAULT_SAVE_DELAY_US), Some(20));
+        assert_eq!(delay.update(20, 2 * DEFAULT_SAVE_DELAY_US), None);
+    }
+
+    #[test]
+    fn test_each_change_restarts_delay() {
+        let mut delay = SaveDelay::new(10u8);
+        let mut saves = Vec::new();
+        // A new level every second for ten seconds, then left alone
+        for t in 0..20 {
+            let level = 20 + t.min(10) as u8;
+            saves.extend(delay.update(level, t * S));
+        }
+        assert_eq!(saves, [30]);
+    }
+
+    #[test]
+    fn test_change_back_cancels_save() {
+        let mut delay = SaveDelay::new(10u8);
+        delay.update(20, 0);
+        assert_eq!(delay.update(10, S), None);
+        assert_eq!(delay.update(10, 10 * S), None);
+    }
+
+    #[test]
+    fn test_set_save_delay() {
+        let mut delay = SaveDelay::new(10u8);
+        delay.set_delay(S);
+        delay.update(20, 0);
+        assert_eq!(delay.update(20, S), Some(20));
+    }
+}
@@ -0,0 +1,400 @@
+//! Finger position on a row (slider) or ring (wheel) of adjacent touch pads.
+//!
+//! Each sample takes the normalised levels of 2–4 `Channel`s, finds the most
+//! strongly touched pad and interpolates between it and its neighbours.
+
+use crate::autorange::DEFAULT_SAMPLE_RATE;
+use crate::channel::LEVEL_ONE;
+use crate::filter::rescale;
+
+/// Most pads a slider or wheel can be built from.
+pub const MAX_PADS: usize = 4;
+/// Position of the far end of a slider. A wheel wraps from here back to 0.
+pub const POSITION_MAX: u8 = 255;
+/// Smallest position change reported while the finger is down, to keep
+/// level jitter from flooding the light with writes.
+pub const POSITION_HYSTERESIS: u8 = 2;
+/// Default travel for a lift to count as a swipe.
+pub const DEFAULT_SWIPE_MIN: u8 = 128;
+/// Default longest touch, in samples at `DEFAULT_SAMPLE_RATE`, that can still
+/// be a swipe (~0.5 s).
+pub const DEFAULT_SWIPE_MAX_SAMPLES: u32 = 150;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Layout {
+    /// Pads in a row: the first is position 0, the last `POSITION_MAX`.
+    Slider,
+    /// Pads in a ring, evenly spaced; the position wraps around.
+    Wheel,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Direction {
+    /// Towards higher positions (clockwise on a wheel).
+    Forward,
+    /// Towards lower positions.
+    Backward,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SliderEvent {
+    /// Finger down at, or moved to, this position.
+    Position(u8),
+    /// Finger lifted after a quick stroke in one direction.
+    Swipe(Direction),
+    /// Finger lifted without swiping.
+    Release,
+}
+
+/// Stroke in progress.
+#[derive(Clone, Copy, Debug)]
+struct Contact {
+    last: u8,
+    reported: u8,
+    travel: i32,
+    samples: u32,
+}
+
+pub struct Slider {
+    layout: Layout,
+    pads: usize,
+    touch_threshold: u32,
+    swipe_min: u8,
+    swipe_max_samples: u32,
+    /// Samples per second `swipe_max_samples` is counted at.
+    sample_rate: u32,
+    contact: Option<Contact>,
+}
+
+impl Slider {
+    /// A slider or wheel over `pads` channels, clamped to `2..=MAX_PADS`.
+    pub fn new(layout: Layout, pads: usize) -> Self {
+        Slider {
+            layout,
+            pads: pads.clamp(2, MAX_PADS),
+            touch_threshold: LEVEL_ONE / 2,
+            swipe_min: DEFAULT_SWIPE_MIN,
+            swipe_max_samples: DEFAULT_SWIPE_MAX_SAMPLES,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            contact: None,
+        }
+    }
+
+    /// Set how far and how fast a stroke must be to count as a swipe, in
+    /// samples at the current `sample_rate`.
+    pub fn set_swipe(&mut self, min_travel: u8, max_samples: u32) {
+        self.swipe_min = min_travel;
+        self.swipe_max_samples = max_samples;
+    }
+
+    /// Samples per second `update` is called at: `DEFAULT_SAMPLE_RATE` until
+    /// set.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Set the rate `update` is called at, rescaling the swipe time to last
+    /// as long as before.
+    pub fn set_sample_rate(&mut self, samples_per_s: u32) {
+        let samples_per_s = samples_per_s.max(1);
+        self.swipe_max_samples = rescale(self.swipe_max_samples, self.sample_rate, samples_per_s);
+        self.sample_rate = samples_per_s;
+    }
+
+    /// Normalised level (as from `Channel::level`) below which a pad counts
+    /// as touched.
+    pub fn set_touch_threshold(&mut self, level: u32) {
+        self.touch_threshold = level;
+    }
+
+    pub fn is_touched(&self) -> bool {
+        self.contact.is_some()
+    }
+
+    /// Feed one sample of every pad's normalised level, in pad order.
+    /// Missing pads read as untouched.
+    pub fn update(&mut self, levels: &[u32]) -> Option<SliderEvent> {
+        let mut strength = [0u32; MAX_PADS];
+        for (s, &level) in strength.iter_mut().zip(levels).take(self.pads) {
+            *s = LEVEL_ONE.saturating_sub(level);
+        }
+        let strongest = (0..self.pads).max_by_key(|&i| strength[i]).unwrap_or(0);
+
+        // A finger between two pads touches each only half as strongly, so
+        // presence goes by the combined strength around the strongest pad
+        let (position, total) = self.position(&strength, strongest);
+        if total <= LEVEL_ONE - self.touch_threshold {
+            return self.contact.take().map(|contact| self.lift(contact));
+        }
+
+        match self.contact.as_mut() {
+            None => {
+                self.contact = Some(Contact {
+                    last: position,
+                    reported: position,
+                    travel: 0,
+                    samples: 1,
+                });
+                Some(SliderEvent::Position(position))
+            }
+            Some(contact) => {
+                contact.travel += distance(self.layout, contact.last, position);
+                contact.last = position;
+                contact.samples = contact.samples.saturating_add(1);
+                let moved = distance(self.layout, contact.reported, position).unsigned_abs();
+                if moved >= POSITION_HYSTERESIS as u32 {
+                    contact.reported = position;
+                    Some(SliderEvent::Position(position))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    fn lift(&self, contact: Contact) -> SliderEvent {
+        let quick = contact.samples <= self.swipe_max_samples;
+        match contact.travel {
+            t if quick && t >= self.swipe_min as i32 => SliderEvent::Swipe(Direction::Forward),
+            t if quick && -t >= self.swipe_min as i32 => SliderEvent::Swipe(Direction::Backward),
+            _ => SliderEvent::Release,
+        }
+    }
+
+    /// Centroid of the strongest pad and its neighbours, and their combined
+    /// strength.
+    fn position(&self, strength: &[u32; MAX_PADS], strongest: usize) -> (u8, u32) {
+        let n = self.pads;
+        let (spacing, prev, next) = match self.layout {
+            Layout::Slider => (
+                POSITION_MAX as i32 / (n as i32 - 1),
+                strongest.checked_sub(1).map_or(0, |i| strength[i]),
+                if strongest + 1 < n { strength[strongest + 1] } else { 0 },
+            ),
+            Layout::Wheel => (
+                (POSITION_MAX as i32 + 1) / n as i32,
+                strength[(strongest + n - 1) % n],
+                strength[(strongest + 1) % n],
+            ),
+        };
+        let total = prev + strength[strongest] + next;
+        if total == 0 {
+            return (0, 0);
+        }
+        let offset = (spacing as i64 * (next as i64 - prev as i64) / total as i64) as i32;
+        let centre = match self.layout {
+            // The last pad sits exactly at the end even when 255 does not divide evenly
+            Layout::Slider => POSITION_MAX as i32 * strongest as i32 / (n as i32 - 1),
+            Layout::Wheel => spacing * strongest as i32,
+        };
+        let position = match self.layout {
+            Layout::Slider => (centre + offset).clamp(0, POSITION_MAX as i32) as u8,
+            Layout::Wheel => (centre + offset).rem_euclid(POSITION_MAX as i32 + 1) as u8,
+        };
+        (position, total)
+    }
+}
+
+/// Signed travel from `from` to `to`, the short way round on a wheel.
+fn distance(layout: Layout, from: u8, to: u8) -> i32 {
+    match layout {
+        Layout::Slider => to as i32 - from as i32,
+        Layout::Wheel => to.wrapping_sub(from) as i8 as i32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Levels for a finger centred at `position` (slider units) over pads
+    /// `spacing` apart, falling off linearly to untouched one spacing away.
+    fn finger(pads: usize, layout: Layout, position: i32) -> Vec<u32> {
+        let spacing = match layout {
+            Layout::Slider => POSITION_MAX as i32 / (pads as i32 - 1),
+            Layout::Wheel => 256 / pads as i32,
+        };
+        (0..pads)
+            .map(|i| {
+                let centre = match layout {
+                    Layout::Slider => POSITION_MAX as i32 * i as i32 / (pads as i32 - 1),
+                    Layout::Wheel => spacing * i as i32,
+                };
+                let d = match layout {
+                    Layout::Slider => (position - centre).abs(),
+                    Layout::Wheel => distance(layout, centre as u8, position.rem_euclid(256) as u8).abs(),
+                };
+                let strength = (LEVEL_ONE as i64 * (spacing - d).max(0) as i64 / spacing as i64) as u32;
+                LEVEL_ONE - strength
+            })
+            .collect()
+    }
+
+    fn untouched(pads: usize) -> Vec<u32> {
+        vec![LEVEL_ONE; pads]
+    }
+
+    /// Sweep a finger from `from` to `to` over `samples` samples, then lift.
+    fn sweep(slider: &mut Slider, pads: usize, layout: Layout, from: i32, to: i32, samples: i32) -> Vec<SliderEvent> {
+        let mut events: Vec<_> = (0..=samples)
+            .filter_map(|i| slider.update(&finger(pads, layout, from + (to - from) * i / samples)))
+            .collect();
+        events.extend(slider.update(&untouched(pads)));
+        events
+    }
+
+    fn positions(events: &[SliderEvent]) -> Vec<u8> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                SliderEvent::Position(p) => Some(*p),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // --- Position ---
+
+    #[test]
+    fn test_untouched_is_silent() {
+        let mut slider = Slider::new(Layout::Slider, 3);
+        for _ in 0..10 {
+            assert_eq!(slider.update(&untouched(3)), None);
+        }
+        assert!(!slider.is_touched());
+    }
+
+    #[test]
+    fn test_finger_on_each_pad() {
+        for pads in 2..=MAX_PADS {
+            for i in 0..pads {
+                let mut slider = Slider::new(Layout::Slider, pads);
+                let mut levels = untouched(pads);
+                levels[i] = 0;
+                let expected = (POSITION_MAX as usize * i / (pads - 1)) as u8;
+                assert_eq!(slider.update(&levels), Some(SliderEvent::Position(expected)), "pads {} pad {}", pads, i);
+            }
+        }
+    }
+
+    #[test]
+    fn test_interpolates_between_pads() {
+        for pads in 2..=MAX_PADS {
+            for position in 0..=POSITION_MAX as i32 {
+                let mut slider = Slider::new(Layout::Slider, pads);
+                let Some(SliderEvent::Position(p)) = slider.update(&finger(pads, Layout::Slider, position)) else {
+                    panic!("no contact at {}", position);
+                };
+                assert!((p as i32 - position).abs() <= 2, "pads {}: {} read as {}", pads, position, p);
+            }
+        }
+    }
+
+    #[test]
+    fn test_light_touch_below_threshold_is_ignored() {
+        let mut slider = Slider::new(Layout::Slider, 3);
+        assert_eq!(slider.update(&[LEVEL_ONE / 2 + 1, LEVEL_ONE, LEVEL_ONE]), None);
+        let quarter = LEVEL_ONE - LEVEL_ONE / 4;
+        assert_eq!(slider.update(&[LEVEL_ONE, quarter, quarter]), None);
+        assert_eq!(slider.update(&[LEVEL_ONE / 2 - 1, LEVEL_ONE, LEVEL_ONE]), Some(SliderEvent::Position(0)));
+    }
+
+    // --- Sweeps ---
+
+    #[test]
+    fn test_slow_sweep_tracks_finger_and_releases() {
+        let mut slider = Slider::new(Layout::Slider, 4);
+        let events = sweep(&mut slider, 4, Layout::Slider, 0, 255, 1000);
+        let reported = positions(&events);
+        assert_eq!(reported.first(), Some(&0));
+        assert!(*reported.last().unwrap() >= 253);
+        assert!(reported.windows(2).all(|w| w[1] > w[0]), "{:?}", reported);
+        assert!(reported.windows(2).all(|w| w[1] - w[0] <= 2 * POSITION_HYSTERESIS));
+        assert_eq!(events.last(), Some(&SliderEvent::Release));
+    }
+
+    #[test]
+    fn test_holding_still_reports_once() {
+        let mut slider = Slider::new(Layout::Slider, 3);
+        let events: Vec<_> = (0..100).filter_map(|_| slider.update(&finger(3, Layout::Slider, 100))).collect();
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    fn test_quick_sweeps_are_swipes() {
+        let mut slider = Slider::new(Layout::Slider, 3);
+        let events = sweep(&mut slider, 3, Layout::Slider, 20, 230, 60);
+        assert_eq!(events.last(), Some(&SliderEvent::Swipe(Direction::Forward)));
+        let events = sweep(&mut slider, 3, Layout::Slider, 230, 20, 60);
+        assert_eq!(events.last(), Some(&SliderEvent::Swipe(Direction::Backward)));
+    }
+
+    #[test]
+    fn test_short_or_slow_strokes_are_not_swipes() {
+        let mut slider = Slider::new(Layout::Slider, 3);
+        let events = sweep(&mut slider, 3, Layout::Slider, 100, 160, 20);
+        assert_eq!(events.last(), Some(&SliderEvent::Release));
+        let events = sweep(&mut slider, 3, Layout::Slider, 0, 255, 400);
+        assert_eq!(events.last(), Some(&SliderEvent::Release));
+    }
+
+    #[test]
+    fn test_back_and_forth_is_not_a_swipe() {
+        let mut slider = Slider::new(Layout::Slider, 3);
+        let mut events = Vec::new();
+        for (from, to) in [(50, 200), (200, 50)] {
+            for i in 0..=30 {
+                events.extend(slider.update(&finger(3, Layout::Slider, from + (to - from) * i / 30)));
+            }
+        }
+        events.extend(slider.update(&untouched(3)));
+        assert_eq!(events.last(), Some(&SliderEvent::Release));
+    }
+
+    #[test]
+    fn test_swipe_settings() {
+        let mut slider = Slider::new(Layout::Slider, 2);
+        slider.set_swipe(40, 20);
+        let events = sweep(&mut slider, 2, Layout::Slider, 100, 150, 10);
+        assert_eq!(events.last(), Some(&SliderEvent::Swipe(Direction::Forward)));
+    }
+
+    #[test]
+    fn test_swipe_time_follows_sample_rate() {
+        // 0.4 s is quick enough at any rate; ~0.6 s is not
+        let mut slider = Slider::new(Layout::Slider, 3);
+        let events = sweep(&mut slider, 3, Layout::Slider, 20, 230, 120);
+        assert_eq!(events.last(), Some(&SliderEvent::Swipe(Direction::Forward)));
+        slider.set_sample_rate(DEFAULT_SAMPLE_RATE * 4);
+        assert_eq!(slider.sample_rate(), DEFAULT_SAMPLE_RATE * 4);
+        let events = sweep(&mut slider, 3, Layout::Slider, 20, 230, 480);
+        assert_eq!(events.last(), Some(&SliderEvent::Swipe(Direction::Forward)));
+        let events = sweep(&mut slider, 3, Layout::Slider, 20, 230, 700);
+        assert_eq!(events.last(), Some(&SliderEvent::Release));
+    }
+
+    // --- Wheel ---
+
+    #[test]
+    fn test_wheel_tracks_and_wraps() {
+        let mut slider = Slider::new(Layout::Wheel, 4);
+        let mut last = None;
+        for position in 200..330 {
+            if let Some(SliderEvent::Position(p)) = slider.update(&finger(4, Layout::Wheel, position)) {
+                let expected = (position % 256) as u8;
+                assert!(distance(Layout::Wheel, expected, p).abs() <= 2, "{} read as {}", position, p);
+                last = Some(p);
+            }
+        }
+        assert!(last.unwrap() < 80);
+    }
+
+    #[test]
+    fn test_wheel_swipe_across_the_seam() {
+        let mut slider = Slider::new(Layout::Wheel, 3);
+        let events = sweep(&mut slider, 3, Layout::Wheel, 180, 400, 80);
+        assert_eq!(events.last(), Some(&SliderEvent::Swipe(Direction::Forward)));
+        let events = sweep(&mut slider, 3, Layout::Wheel, 300, 100, 80);
+        assert_eq!(events.last(), Some(&SliderEvent::Swipe(Direction::Backward)));
+    }
+}